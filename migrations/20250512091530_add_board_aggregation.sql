@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TYPE score_aggregation AS ENUM('sum', 'best', 'latest', 'average', 'count');
+
+ALTER TABLE leaderboards
+ADD COLUMN aggregation score_aggregation NOT NULL DEFAULT 'sum';
+
+COMMENT ON COLUMN leaderboards.aggregation IS 'How the points of a player are combined into a single score';
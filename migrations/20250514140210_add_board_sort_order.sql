@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TYPE sort_order AS ENUM('descending', 'ascending');
+
+ALTER TABLE leaderboards
+ADD COLUMN sort_order sort_order NOT NULL DEFAULT 'descending';
+
+COMMENT ON COLUMN leaderboards.sort_order IS 'Whether higher or lower scores are ranked first';
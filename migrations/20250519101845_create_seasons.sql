@@ -0,0 +1,32 @@
+-- Add migration script here
+
+CREATE TYPE season_length AS ENUM('day', 'week', 'month');
+
+CREATE TABLE season_schedules(
+    leaderboard INTEGER PRIMARY KEY REFERENCES leaderboards(id),
+    starts_at TIMESTAMPTZ NOT NULL,
+    length season_length NOT NULL
+);
+
+CREATE TABLE seasons(
+    id SERIAL PRIMARY KEY,
+    leaderboard INTEGER NOT NULL REFERENCES leaderboards(id),
+    starts_at TIMESTAMPTZ NOT NULL,
+    ends_at TIMESTAMPTZ NOT NULL,
+    archived_at TIMESTAMPTZ NULL,
+    UNIQUE(leaderboard, starts_at)
+);
+
+ALTER TABLE points
+ADD COLUMN season INTEGER NULL REFERENCES seasons(id);
+
+CREATE TABLE season_standings(
+    season INTEGER NOT NULL REFERENCES seasons(id),
+    rank BIGINT NOT NULL,
+    player UUID NOT NULL REFERENCES users(id),
+    player_alias TEXT NULL,
+    score NUMERIC NOT NULL,
+    PRIMARY KEY(season, player)
+);
+
+COMMENT ON TABLE season_standings IS 'The final standings of a season, frozen when the season ends';
@@ -0,0 +1,4 @@
+-- Add migration script here
+
+ALTER TABLE sessions
+ADD COLUMN expires_at TIMESTAMPTZ NOT NULL DEFAULT now() + INTERVAL '30 days';
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+ALTER TABLE sessions
+RENAME COLUMN token TO token_hash;
+
+UPDATE sessions SET token_hash = encode(sha256(token_hash::BYTEA), 'hex');
+
+COMMENT ON COLUMN sessions.token_hash IS 'Hex encoded SHA-256 hash of the session token';
@@ -0,0 +1,19 @@
+-- Add migration script here
+
+ALTER TABLE users
+ADD COLUMN email_verified_at TIMESTAMPTZ NULL;
+
+CREATE TYPE email_token_purpose AS ENUM('verify_email', 'reset_password');
+
+CREATE TABLE email_tokens(
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose email_token_purpose NOT NULL,
+    email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ NULL
+);
+
+COMMENT ON COLUMN email_tokens.email IS 'The address the token was sent to';
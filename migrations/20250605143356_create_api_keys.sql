@@ -0,0 +1,17 @@
+-- Add migration script here
+
+CREATE TYPE api_key_scope AS ENUM('submit_score', 'manage_boards');
+
+CREATE TABLE api_keys(
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    name TEXT NOT NULL,
+    prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    scopes api_key_scope[] NOT NULL DEFAULT '{}',
+    created_by UUID NOT NULL REFERENCES users(id),
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL,
+    last_used_at TIMESTAMPTZ NULL,
+    revoked_at TIMESTAMPTZ NULL
+);
+
+COMMENT ON COLUMN api_keys.prefix IS 'The start of the key, shown so that keys can be told apart';
@@ -0,0 +1,29 @@
+-- Add migration script here
+
+CREATE TABLE projects(
+    id UUID DEFAULT uuid_generate_v4() PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT now() NOT NULL
+);
+
+-- Everything created before projects belongs to the default project
+INSERT INTO projects(id,name) VALUES('00000000-0000-0000-0000-000000000000','Default');
+
+ALTER TABLE leaderboards
+ADD COLUMN project UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES projects(id);
+
+ALTER TABLE users
+ADD COLUMN project UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES projects(id);
+
+ALTER TABLE api_keys
+ADD COLUMN project UUID NOT NULL DEFAULT '00000000-0000-0000-0000-000000000000' REFERENCES projects(id);
+
+ALTER TABLE leaderboards ALTER COLUMN project DROP DEFAULT;
+ALTER TABLE users ALTER COLUMN project DROP DEFAULT;
+ALTER TABLE api_keys ALTER COLUMN project DROP DEFAULT;
+
+-- Emails are unique within a project, the same person can play several games
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE users ADD CONSTRAINT users_project_email_key UNIQUE(project,email);
+
+CREATE INDEX leaderboards_project_idx ON leaderboards(project);
@@ -0,0 +1,8 @@
+-- Add migration script here
+
+CREATE TYPE user_role AS ENUM('owner', 'admin', 'moderator', 'player');
+
+ALTER TABLE users
+ADD COLUMN role user_role NOT NULL DEFAULT 'player';
+
+COMMENT ON COLUMN users.role IS 'The role of the user in their project';
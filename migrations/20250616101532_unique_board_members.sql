@@ -0,0 +1,9 @@
+-- Add migration script here
+
+-- Players added to a board twice keep their first membership
+DELETE FROM leaderboard_members a
+USING leaderboard_members b
+WHERE a.leaderboard = b.leaderboard AND a.player = b.player AND a.id > b.id;
+
+ALTER TABLE leaderboard_members
+ADD CONSTRAINT leaderboard_members_leaderboard_player_key UNIQUE(leaderboard, player);
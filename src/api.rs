@@ -1,56 +1,584 @@
 use crate::{
-    AppState,
-    auth::{self, User},
-    board::{self, Leaderboard},
+    AppState, ClientError, ClientErrorKind,
+    api_key::{ApiKey, Scope},
+    auth::{self, Caller, CurrentUser, Session, User},
+    board::{
+        self, AroundQuery, BoardOptions, Leaderboard, LeaderboardMember, PlayerWindow, Point,
+        Standing, StandingsQuery,
+    },
+    hub,
+    project::{Project, ProjectId},
+    role::Role,
+    season::{Season, SeasonLength, SeasonSchedule},
+    window::TimeWindow,
 };
-use axum::{Json, extract::State, http::StatusCode};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct CreateBoardPayload {
     pub name: String,
+    #[serde(flatten)]
+    pub options: BoardOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct SubmitScorePayload {
+    /// The player the score belongs to, defaults to the current user
+    pub player: Option<Uuid>,
+    pub value: Decimal,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonSchedulePayload {
+    pub starts_at: DateTime<Utc>,
+    pub length: SeasonLength,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CredentialsPayload {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct UpgradePayload {
+    pub email: Option<String>,
+    pub user_name: Option<String>,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct PasswordResetPayload {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ResetPasswordPayload {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct VerifyEmailPayload {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CreateApiKeyPayload {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// A new API key, the secret key is only returned when it's created
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RenameBoardPayload {
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddMemberPayload {
+    pub player: Uuid,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetRolePayload {
+    pub role: Role,
+}
+
+/// A logged in user and their session
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthResponse {
+    pub user: User,
+    pub session: Session,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct CreateProjectPayload {
+    pub name: String,
+    /// The email the owner of the project logs in with
+    pub email: String,
+    pub password: String,
+}
+
+/// A new project and a session for its owner
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateProjectResponse {
+    pub project: Project,
+    pub owner: User,
+    pub session: Session,
 }
 
 /// Sign up as an anonymous user
 pub async fn anon_sign_up(
     State(state): State<AppState>,
-) -> crate::Result<(StatusCode, Json<User>)> {
-    let user = auth::create_anon_user(&state.pool).await?;
-    let response = (StatusCode::CREATED, Json(user));
+    ProjectId(project): ProjectId,
+) -> crate::Result<(StatusCode, Json<AuthResponse>)> {
+    let user = auth::create_anon_user(project, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+    let response = (StatusCode::CREATED, Json(AuthResponse { user, session }));
 
     Ok(response)
 }
 
+/// Sign up with an email and password
+pub async fn sign_up(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Json(payload): Json<CredentialsPayload>,
+) -> crate::Result<(StatusCode, Json<AuthResponse>)> {
+    let user = auth::create_user(project, &payload.email, &payload.password, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    Ok((StatusCode::CREATED, Json(AuthResponse { user, session })))
+}
+
+/// Register the current anonymous user, keeping their history
+pub async fn upgrade(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    Json(payload): Json<UpgradePayload>,
+) -> crate::Result<Json<User>> {
+    let user = auth::upgrade_anon_user(
+        &current.user,
+        payload.email.as_deref(),
+        payload.user_name.as_deref(),
+        &payload.password,
+        state.pool(),
+    )
+    .await?;
+
+    Ok(Json(user))
+}
+
+/// Merge the current anonymous user into an existing account, the
+/// anonymous session is replaced with a session for the existing account
+pub async fn merge(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    Json(payload): Json<CredentialsPayload>,
+) -> crate::Result<Json<AuthResponse>> {
+    let user = auth::merge_anon_user(
+        &current.user,
+        &payload.email,
+        &payload.password,
+        state.pool(),
+    )
+    .await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    Ok(Json(AuthResponse { user, session }))
+}
+
+/// Log in with an email and password
+pub async fn login(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Json(payload): Json<CredentialsPayload>,
+) -> crate::Result<Json<AuthResponse>> {
+    let (user, session) =
+        auth::login(project, &payload.email, &payload.password, state.pool()).await?;
+
+    Ok(Json(AuthResponse { user, session }))
+}
+
+/// End the current session
+pub async fn logout(
+    State(state): State<AppState>,
+    current: CurrentUser,
+) -> crate::Result<StatusCode> {
+    Session::revoke(current.session_id, state.pool()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Email a password reset token
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Json(payload): Json<PasswordResetPayload>,
+) -> crate::Result<StatusCode> {
+    auth::request_password_reset(project, &payload.email, state.mailer(), state.pool()).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Set a new password with a password reset token, this logs out
+/// all of the user's sessions.
+pub async fn reset_password(
+    State(state): State<AppState>,
+    Json(payload): Json<ResetPasswordPayload>,
+) -> crate::Result<StatusCode> {
+    auth::reset_password(&payload.token, &payload.password, state.pool()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Email a verification token to the current user
+pub async fn request_email_verification(
+    State(state): State<AppState>,
+    current: CurrentUser,
+) -> crate::Result<StatusCode> {
+    auth::request_email_verification(&current.user, state.mailer(), state.pool()).await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Verify an email with a verification token
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Json(payload): Json<VerifyEmailPayload>,
+) -> crate::Result<Json<User>> {
+    let user = auth::verify_email(&payload.token, state.pool()).await?;
+
+    Ok(Json(user))
+}
+
+/// Get the currently logged in user
+pub async fn get_current_user(current: CurrentUser) -> Json<User> {
+    Json(current.user)
+}
+
+/// Create an API key, only users that can manage API keys can create them
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    current: CurrentUser,
+    Json(payload): Json<CreateApiKeyPayload>,
+) -> crate::Result<(StatusCode, Json<CreateApiKeyResponse>)> {
+    if current.user.is_anonymous {
+        let error = ClientError::new(
+            "Anonymous users cannot create API keys",
+            ClientErrorKind::Forbidden,
+        );
+        return Err(error.into());
+    }
+
+    // Keys can't do more than the user creating them
+    let role = current.user.role;
+    for scope in &payload.scopes {
+        if !scope
+            .permissions()
+            .iter()
+            .all(|permission| role.can(*permission))
+        {
+            let error = ClientError::new(
+                "Your role can't create keys with this scope",
+                ClientErrorKind::Forbidden,
+            );
+            return Err(error.into());
+        }
+    }
+
+    let (api_key, key) = ApiKey::create(
+        &payload.name,
+        &payload.scopes,
+        current.user.id,
+        state.pool(),
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateApiKeyResponse { api_key, key }),
+    ))
+}
+
+/// Get the API keys created by the current user
+pub async fn get_api_keys(
+    State(state): State<AppState>,
+    current: CurrentUser,
+) -> crate::Result<Json<Vec<ApiKey>>> {
+    let keys = ApiKey::created_by(current.user.id, state.pool()).await?;
+
+    Ok(Json(keys))
+}
+
+/// Revoke an API key created by the current user
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    current: CurrentUser,
+) -> crate::Result<Json<ApiKey>> {
+    let key = ApiKey::revoke(id, current.user.id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("API key not found"))?;
+
+    Ok(Json(key))
+}
+
 /// Create a leaderboard
 pub async fn create_board(
     State(state): State<AppState>,
+    caller: Caller,
     Json(payload): Json<CreateBoardPayload>,
 ) -> crate::Result<(StatusCode, Json<Leaderboard>)> {
-    let board = board::Leaderboard::new(&payload.name, state.pool()).await?;
+    let board = board::Leaderboard::with_options(
+        caller.project(),
+        &payload.name,
+        &payload.options,
+        state.pool(),
+    )
+    .await?;
 
     Ok((StatusCode::CREATED, Json(board)))
 }
 
-/// Get all leaderboards
+/// Rename a leaderboard
+pub async fn rename_board(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+    Json(payload): Json<RenameBoardPayload>,
+) -> crate::Result<Json<Leaderboard>> {
+    let mut board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    board.rename(&payload.name, state.pool()).await?;
+
+    Ok(Json(board))
+}
+
+/// Delete a leaderboard and all of its scores
+pub async fn delete_board(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+) -> crate::Result<StatusCode> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    board.delete(state.pool()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Add a player to a leaderboard
+pub async fn add_board_member(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+    Json(payload): Json<AddMemberPayload>,
+) -> crate::Result<(StatusCode, Json<LeaderboardMember>)> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    board.add_member(payload.player, state.pool()).await?;
+    let member = board
+        .get_member(payload.player, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Player not found"))?;
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
+
+/// Remove a player and their scores from a leaderboard
+pub async fn remove_board_member(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path((id, player)): Path<(i32, Uuid)>,
+) -> crate::Result<StatusCode> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    if !board.remove_member(player, state.pool()).await? {
+        return Err(ClientError::not_found("Player is not a member of this leaderboard").into());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Set the role of a user in the current project, users can't
+/// change their own role.
+pub async fn set_user_role(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SetRolePayload>,
+) -> crate::Result<Json<User>> {
+    if matches!(&caller, Caller::User(current) if current.user.id == id) {
+        let error = ClientError::new("Cannot change your own role", ClientErrorKind::Forbidden);
+        return Err(error.into());
+    }
+
+    let user = auth::set_role(caller.project(), id, payload.role, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("User not found"))?;
+
+    Ok(Json(user))
+}
+
+/// Create a project along with its owner
+pub async fn create_project(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateProjectPayload>,
+) -> crate::Result<(StatusCode, Json<CreateProjectResponse>)> {
+    let (project, owner) = Project::create_with_owner(
+        &payload.name,
+        &payload.email,
+        &payload.password,
+        state.pool(),
+    )
+    .await?;
+    let session = Session::create(owner.id, state.pool()).await?;
+
+    let response = CreateProjectResponse {
+        project,
+        owner,
+        session,
+    };
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Get all the leaderboards in the project
 pub async fn get_leaderboards(
     State(state): State<AppState>,
+    ProjectId(project): ProjectId,
 ) -> crate::Result<Json<Vec<Leaderboard>>> {
-    let boards: Vec<Leaderboard> = sqlx::query_as("SELECT * FROM leaderboards")
-        .fetch_all(state.pool())
-        .await?;
+    let boards = Leaderboard::all(project, state.pool()).await?;
 
     Ok(Json(boards))
 }
 
+/// Submit a score to a leaderboard, users can only submit their own scores
+/// while API keys can submit scores for any player.
+pub async fn submit_score(
+    State(state): State<AppState>,
+    Path(id): Path<i32>,
+    caller: Caller,
+    Json(payload): Json<SubmitScorePayload>,
+) -> crate::Result<(StatusCode, Json<Point>)> {
+    caller.require_scope(Scope::SubmitScore)?;
+    let player = caller.score_player(payload.player)?;
+
+    let board = Leaderboard::get(caller.project(), id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    let point = hub::submit_score(state.hub(), &board, player, payload.value, state.pool()).await?;
+
+    Ok((StatusCode::CREATED, Json(point)))
+}
+
+/// Get the ranked standings of a leaderboard
+pub async fn get_standings(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+    Query(query): Query<StandingsQuery>,
+    Query(window): Query<TimeWindow>,
+) -> crate::Result<Json<Vec<Standing>>> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    let standings = board.standings(&query, &window, state.pool()).await?;
+
+    Ok(Json(standings))
+}
+
+/// Get a player's rank and the players directly above and below them
+pub async fn get_standings_around(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path((id, player)): Path<(i32, Uuid)>,
+    Query(query): Query<AroundQuery>,
+    Query(window): Query<TimeWindow>,
+) -> crate::Result<Json<PlayerWindow>> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    let window = board
+        .around_player(player, &query, &window, state.pool())
+        .await?
+        .ok_or(ClientError::not_found(
+            "Player has no score on this leaderboard",
+        ))?;
+
+    Ok(Json(window))
+}
+
+/// Set the season schedule of a leaderboard
+pub async fn set_season_schedule(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+    Json(payload): Json<SeasonSchedulePayload>,
+) -> crate::Result<Json<SeasonSchedule>> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    let schedule =
+        SeasonSchedule::set(&board, payload.starts_at, payload.length, state.pool()).await?;
+
+    Ok(Json(schedule))
+}
+
+/// Get all the seasons of a leaderboard
+pub async fn get_seasons(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+) -> crate::Result<Json<Vec<Season>>> {
+    let board = Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found"))?;
+
+    let seasons = Season::all(&board, state.pool()).await?;
+
+    Ok(Json(seasons))
+}
+
+/// Get the final standings of a season that has ended
+pub async fn get_season_standings(
+    State(state): State<AppState>,
+    ProjectId(project): ProjectId,
+    Path(id): Path<i32>,
+    Query(query): Query<StandingsQuery>,
+) -> crate::Result<Json<Vec<Standing>>> {
+    let season = Season::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Season not found"))?;
+
+    let standings = season
+        .standings(&query, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Season has not been archived yet"))?;
+
+    Ok(Json(standings))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::project::DEFAULT_PROJECT;
     use sqlx::PgPool;
 
     #[sqlx::test(migrations = "./migrations")]
     async fn sign_up_anonymously(pool: PgPool) -> crate::Result<()> {
         let state = AppState::with_pool(pool).await?;
-        let (status, _) = anon_sign_up(State(state)).await?;
+        let (status, _) = anon_sign_up(State(state), ProjectId(DEFAULT_PROJECT)).await?;
 
         assert_eq!(status, StatusCode::CREATED);
         Ok(())
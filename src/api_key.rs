@@ -0,0 +1,246 @@
+//! API keys let servers call the api without a user session, e.g. a
+//! dedicated game server submitting scores for its players.
+//!
+//! Keys are sent in the `x-api-key` header and are only stored hashed.
+use crate::{
+    ClientError, ClientErrorKind,
+    role::{Permission, Role},
+    token,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, prelude::FromRow};
+use uuid::Uuid;
+
+/// The header API keys are sent in
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The start of every key, makes leaked keys easy to find
+pub const KEY_PREFIX: &str = "sbk_";
+
+/// What an API key is allowed to do
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "api_key_scope", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum Scope {
+    /// Submit scores for any player
+    SubmitScore,
+    /// Create and manage leaderboards
+    ManageBoards,
+}
+
+impl Scope {
+    /// The permissions a key with the scope has, as long as the user
+    /// that created it has them too.
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Self::SubmitScore => &[],
+            Self::ManageBoards => &[
+                Permission::CreateBoard,
+                Permission::UpdateBoard,
+                Permission::DeleteBoard,
+                Permission::ManageMembers,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<Scope>,
+    pub created_by: Uuid,
+    /// The project of the user that created the key
+    pub project: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// The current role of the user that created the key
+    #[serde(skip)]
+    pub creator_role: Role,
+}
+
+impl ApiKey {
+    /// Create a key in the project of the user creating it, returns the
+    /// key and the secret which is only available now. Returns a
+    /// [`ClientErrorKind::NotFound`] error if there's no such user.
+    pub async fn create(
+        name: &str,
+        scopes: &[Scope],
+        created_by: Uuid,
+        pool: &PgPool,
+    ) -> crate::Result<(Self, String)> {
+        let secret = format!("{KEY_PREFIX}{}", token::generate(token::TOKEN_BYTES));
+        let prefix = &secret[..KEY_PREFIX.len() + 6];
+
+        let key: Option<ApiKey> = sqlx::query_as(
+            "WITH key AS (
+                INSERT INTO api_keys(name,prefix,key_hash,scopes,created_by,project) 
+                SELECT $1,$2,$3,$4,id,project FROM users WHERE id = $5 
+                RETURNING *
+            )
+            SELECT key.*, u.role AS creator_role FROM key JOIN users u ON u.id = key.created_by",
+        )
+        .bind(name)
+        .bind(prefix)
+        .bind(token::hash(&secret))
+        .bind(scopes)
+        .bind(created_by)
+        .fetch_optional(pool)
+        .await?;
+
+        let key = key.ok_or(ClientError::not_found("User not found"))?;
+        Ok((key, secret))
+    }
+
+    /// Get the unrevoked key with the secret, marking it as used. Keys stop
+    /// working once their creator can no longer manage API keys.
+    pub async fn authenticate(secret: &str, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let key: Option<ApiKey> = sqlx::query_as(
+            "SELECT k.*, u.role AS creator_role FROM api_keys k 
+            JOIN users u ON u.id = k.created_by 
+            WHERE k.key_hash = $1 AND k.revoked_at IS NULL",
+        )
+        .bind(token::hash(secret))
+        .fetch_optional(pool)
+        .await?;
+
+        let Some(mut key) = key.filter(|key| key.creator_role.can(Permission::ManageApiKeys))
+        else {
+            return Ok(None);
+        };
+
+        let last_used_at: DateTime<Utc> = sqlx::query_scalar(
+            "UPDATE api_keys SET last_used_at = now() WHERE id = $1 RETURNING last_used_at",
+        )
+        .bind(key.id)
+        .fetch_one(pool)
+        .await?;
+        key.last_used_at = Some(last_used_at);
+
+        Ok(Some(key))
+    }
+
+    /// Get an unrevoked key by it's id, returns `None` if the key was
+    /// revoked or its creator can no longer manage API keys.
+    pub async fn get_active(id: Uuid, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let key: Option<ApiKey> = sqlx::query_as(
+            "SELECT k.*, u.role AS creator_role FROM api_keys k 
+            JOIN users u ON u.id = k.created_by 
+            WHERE k.id = $1 AND k.revoked_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(key.filter(|key| key.creator_role.can(Permission::ManageApiKeys)))
+    }
+
+    /// Get all the keys created by a user, including revoked keys
+    pub async fn created_by(user_id: Uuid, pool: &PgPool) -> crate::Result<Vec<Self>> {
+        let keys: Vec<ApiKey> = sqlx::query_as(
+            "SELECT k.*, u.role AS creator_role FROM api_keys k 
+                JOIN users u ON u.id = k.created_by 
+                WHERE k.created_by = $1 
+                ORDER BY k.created_at",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Revoke a key created by the user, returns `None` if there's no
+    /// such key.
+    pub async fn revoke(id: Uuid, user_id: Uuid, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let key: Option<ApiKey> = sqlx::query_as(
+            "UPDATE api_keys k SET revoked_at = COALESCE(k.revoked_at, now()) 
+            FROM users u 
+            WHERE k.id = $1 AND k.created_by = $2 AND u.id = k.created_by 
+            RETURNING k.*, u.role AS creator_role",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    /// Check if the key has a scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Returns a [`ClientErrorKind::Forbidden`] error if the key
+    /// doesn't have the scope.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), ClientError> {
+        if self.has_scope(scope) {
+            return Ok(());
+        }
+
+        Err(ClientError::new(
+            "API key is missing a required scope",
+            ClientErrorKind::Forbidden,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{create_user, set_role};
+    use crate::project::DEFAULT_PROJECT;
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn create_and_revoke_a_key(pool: PgPool) -> crate::Result<()> {
+        let user = create_user(DEFAULT_PROJECT, "admin@example.com", "hunter22", &pool).await?;
+        set_role(DEFAULT_PROJECT, user.id, Role::Admin, &pool).await?;
+        let (key, secret) = ApiKey::create("Server", &[Scope::SubmitScore], user.id, &pool).await?;
+        assert_eq!(key.creator_role, Role::Admin);
+        assert!(secret.starts_with(&key.prefix));
+        assert!(key.has_scope(Scope::SubmitScore));
+        assert!(key.require_scope(Scope::ManageBoards).is_err());
+
+        let authenticated = ApiKey::authenticate(&secret, &pool).await?.unwrap();
+        assert_eq!(authenticated.id, key.id);
+        assert!(authenticated.last_used_at.is_some());
+
+        let other = create_user(DEFAULT_PROJECT, "other@example.com", "hunter22", &pool).await?;
+        assert!(ApiKey::revoke(key.id, other.id, &pool).await?.is_none());
+
+        ApiKey::revoke(key.id, user.id, &pool).await?.unwrap();
+        assert!(ApiKey::authenticate(&secret, &pool).await?.is_none());
+        assert_eq!(ApiKey::created_by(user.id, &pool).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn keys_stop_working_when_the_creator_is_demoted(pool: PgPool) -> crate::Result<()> {
+        let user = create_user(DEFAULT_PROJECT, "admin@example.com", "hunter22", &pool).await?;
+        set_role(DEFAULT_PROJECT, user.id, Role::Admin, &pool).await?;
+        let (_, secret) = ApiKey::create("Server", &[Scope::ManageBoards], user.id, &pool).await?;
+        assert!(ApiKey::authenticate(&secret, &pool).await?.is_some());
+
+        set_role(DEFAULT_PROJECT, user.id, Role::Moderator, &pool).await?;
+        assert!(ApiKey::authenticate(&secret, &pool).await?.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn create_a_key_for_a_missing_user(pool: PgPool) -> crate::Result<()> {
+        let result = ApiKey::create("Server", &[Scope::SubmitScore], Uuid::now_v7(), &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::NotFound
+        ));
+
+        Ok(())
+    }
+}
@@ -1,9 +1,27 @@
-use base64::engine::{Engine, general_purpose::URL_SAFE};
-use chrono::{DateTime, Utc};
+use crate::{
+    AppState, ClientError, ClientErrorKind,
+    api_key::{API_KEY_HEADER, ApiKey, KEY_PREFIX, Scope},
+    mail::{Email, Mailer},
+    role::{Permission, Role},
+    token,
+};
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{header::AUTHORIZATION, request::Parts},
+};
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{PgPool, prelude::FromRow};
+use sqlx::{PgExecutor, PgPool, prelude::FromRow};
+use std::sync::LazyLock;
 use uuid::Uuid;
 
+/// The minimum number of characters in a password
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
 #[derive(Debug, Serialize, Deserialize, FromRow, Default)]
 pub struct User {
     pub id: Uuid,
@@ -11,35 +29,1134 @@ pub struct User {
     pub user_name: Option<String>,
     pub created_at: DateTime<Utc>,
     pub phone_number: Option<String>,
+    #[serde(skip_serializing, default)]
     pub encrypted_password: Option<String>,
     pub is_anonymous: bool,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// The project the user plays in
+    pub project: Uuid,
+    /// The role of the user in their project
+    pub role: Role,
+}
+
+/// A logged in session of a user, the session token is sent as a
+/// bearer token in the `Authorization` header.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// The session token, only the hash of the token is stored so
+    /// this is empty for sessions that weren't just created.
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
 }
 
-/// Create an anonymous user in the database
-pub async fn create_anon_user(pool: &PgPool) -> crate::Result<User> {
-    let user =
-        sqlx::query_as::<_, User>("INSERT INTO users(is_anonymous) VALUES(true) RETURNING *")
+impl Session {
+    /// Start a new session for a user
+    pub async fn create(user_id: Uuid, pool: &PgPool) -> crate::Result<Self> {
+        let token = token::generate(token::TOKEN_BYTES);
+        let mut session: Session = sqlx::query_as(
+            "INSERT INTO sessions(user_id,token_hash) 
+            VALUES($1,$2) 
+            RETURNING *",
+        )
+        .bind(user_id)
+        .bind(token::hash(&token))
+        .fetch_one(pool)
+        .await?;
+        session.token = token;
+
+        Ok(session)
+    }
+
+    /// Get an unexpired session and it's user, returns `None` if the
+    /// token doesn't belong to a session.
+    pub async fn get_user(token: &str, pool: &PgPool) -> crate::Result<Option<(Session, User)>> {
+        let session: Option<Session> =
+            sqlx::query_as("SELECT * FROM sessions WHERE token_hash = $1 AND expires_at > now()")
+                .bind(token::hash(token))
+                .fetch_optional(pool)
+                .await?;
+
+        Self::with_user(session, pool).await
+    }
+
+    /// Get an unexpired session by it's id along with it's user
+    pub async fn get_user_by_id(id: Uuid, pool: &PgPool) -> crate::Result<Option<(Session, User)>> {
+        let session: Option<Session> =
+            sqlx::query_as("SELECT * FROM sessions WHERE id = $1 AND expires_at > now()")
+                .bind(id)
+                .fetch_optional(pool)
+                .await?;
+
+        Self::with_user(session, pool).await
+    }
+
+    async fn with_user(
+        session: Option<Session>,
+        pool: &PgPool,
+    ) -> crate::Result<Option<(Session, User)>> {
+        let Some(session) = session else {
+            return Ok(None);
+        };
+
+        let user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+            .bind(session.user_id)
             .fetch_one(pool)
             .await?;
 
+        Ok(Some((session, user)))
+    }
+
+    /// End a session
+    pub async fn revoke(id: Uuid, pool: &PgPool) -> crate::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Extracts the user of the session in the `Authorization` header,
+/// rejects the request if there's no valid session.
+///
+/// Use `Option<CurrentUser>` to accept unauthenticated requests, requests
+/// with an invalid session are still rejected.
+#[derive(Debug)]
+pub struct CurrentUser {
+    pub user: User,
+    pub session_id: Uuid,
+}
+
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> crate::Result<Self> {
+        match <Self as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state).await?
+        {
+            Some(user) => Ok(user),
+            None => {
+                let error = ClientError::new("Missing bearer token", ClientErrorKind::Unauthorized);
+                Err(error.into())
+            }
+        }
+    }
+}
+
+impl OptionalFromRequestParts<AppState> for CurrentUser {
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> crate::Result<Option<Self>> {
+        let Some(token) = bearer_token(parts)? else {
+            return Ok(None);
+        };
+
+        match Session::get_user(token, state.pool()).await? {
+            Some((session, user)) => Ok(Some(CurrentUser {
+                user,
+                session_id: session.id,
+            })),
+            None => {
+                let error =
+                    ClientError::new("Invalid or expired session", ClientErrorKind::Unauthorized);
+                Err(error.into())
+            }
+        }
+    }
+}
+
+/// Who made a request, either a user with a session or a server
+/// with an API key in the `x-api-key` header.
+///
+/// Use `Option<Caller>` to accept unauthenticated requests, requests
+/// with invalid credentials are still rejected.
+#[derive(Debug)]
+pub enum Caller {
+    User(CurrentUser),
+    ApiKey(ApiKey),
+}
+
+impl Caller {
+    /// Get the project the caller belongs to
+    pub fn project(&self) -> Uuid {
+        match self {
+            Self::User(current) => current.user.project,
+            Self::ApiKey(key) => key.project,
+        }
+    }
+
+    /// Returns a [`ClientErrorKind::Forbidden`] error if the caller doesn't
+    /// have the permission.
+    pub fn require(&self, permission: Permission) -> Result<(), ClientError> {
+        let allowed = match self {
+            Self::User(current) => current.user.role.can(permission),
+            Self::ApiKey(key) => {
+                key.creator_role.can(permission)
+                    && key
+                        .scopes
+                        .iter()
+                        .any(|scope| scope.permissions().contains(&permission))
+            }
+        };
+
+        if allowed {
+            return Ok(());
+        }
+
+        Err(ClientError::new(
+            "You don't have permission to do this",
+            ClientErrorKind::Forbidden,
+        ))
+    }
+
+    /// Returns a [`ClientErrorKind::Forbidden`] error if the caller is an
+    /// API key without the scope, users are checked by each route.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), ClientError> {
+        match self {
+            Self::User(_) => Ok(()),
+            Self::ApiKey(key) => key.require_scope(scope),
+        }
+    }
+
+    /// Get the player a score is submitted for, users can only submit their
+    /// own scores and API keys have to name the player.
+    pub fn score_player(&self, player: Option<Uuid>) -> Result<Uuid, ClientError> {
+        match (self, player) {
+            (Self::User(current), player) => {
+                let player = player.unwrap_or(current.user.id);
+                if player != current.user.id {
+                    return Err(ClientError::new(
+                        "Cannot submit scores for other players",
+                        ClientErrorKind::Forbidden,
+                    ));
+                }
+                Ok(player)
+            }
+            (Self::ApiKey(_), Some(player)) => Ok(player),
+            (Self::ApiKey(_), None) => Err(ClientError::new(
+                "A player is required when using an API key",
+                ClientErrorKind::InvalidInput,
+            )),
+        }
+    }
+
+    /// Load the caller again so that sessions and keys revoked since it
+    /// authenticated are rejected and role changes are picked up, returns
+    /// a [`ClientErrorKind::Unauthorized`] error if they're revoked.
+    pub async fn refresh(&self, pool: &PgPool) -> crate::Result<Self> {
+        let caller = match self {
+            Self::User(current) => Session::get_user_by_id(current.session_id, pool)
+                .await?
+                .map(|(session, user)| {
+                    Self::User(CurrentUser {
+                        user,
+                        session_id: session.id,
+                    })
+                }),
+            Self::ApiKey(key) => ApiKey::get_active(key.id, pool).await?.map(Self::ApiKey),
+        };
+
+        caller.ok_or_else(|| {
+            let error = ClientError::new(
+                "The session or API key is no longer valid",
+                ClientErrorKind::Unauthorized,
+            );
+            error.into()
+        })
+    }
+
+    /// Authenticate a session token or API key, API keys are told apart
+    /// by their [`KEY_PREFIX`].
+    pub async fn authenticate(token: &str, pool: &PgPool) -> crate::Result<Self> {
+        let token = token.trim();
+        if token.starts_with(KEY_PREFIX) {
+            return match ApiKey::authenticate(token, pool).await? {
+                Some(key) => Ok(Self::ApiKey(key)),
+                None => {
+                    let error = ClientError::new(
+                        "Invalid or revoked API key",
+                        ClientErrorKind::Unauthorized,
+                    );
+                    Err(error.into())
+                }
+            };
+        }
+
+        match Session::get_user(token, pool).await? {
+            Some((session, user)) => Ok(Self::User(CurrentUser {
+                user,
+                session_id: session.id,
+            })),
+            None => {
+                let error =
+                    ClientError::new("Invalid or expired session", ClientErrorKind::Unauthorized);
+                Err(error.into())
+            }
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for Caller {
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> crate::Result<Self> {
+        match <Self as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state).await?
+        {
+            Some(caller) => Ok(caller),
+            None => {
+                let error = ClientError::new(
+                    "Missing bearer token or API key",
+                    ClientErrorKind::Unauthorized,
+                );
+                Err(error.into())
+            }
+        }
+    }
+}
+
+impl OptionalFromRequestParts<AppState> for Caller {
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> crate::Result<Option<Self>> {
+        let Some(header) = parts.headers.get(API_KEY_HEADER) else {
+            let user = <CurrentUser as OptionalFromRequestParts<AppState>>::from_request_parts(
+                parts, state,
+            )
+            .await?;
+            return Ok(user.map(Self::User));
+        };
+
+        let key = match header.to_str() {
+            Ok(secret) => ApiKey::authenticate(secret.trim(), state.pool()).await?,
+            Err(_) => None,
+        };
+
+        match key {
+            Some(key) => Ok(Some(Self::ApiKey(key))),
+            None => {
+                let error =
+                    ClientError::new("Invalid or revoked API key", ClientErrorKind::Unauthorized);
+                Err(error.into())
+            }
+        }
+    }
+}
+
+/// Get the token from an `Authorization: Bearer <token>` header
+pub fn bearer_token(parts: &Parts) -> Result<Option<&str>, ClientError> {
+    let Some(header) = parts.headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    header
+        .to_str()
+        .ok()
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(|token| Some(token.trim()))
+        .ok_or(ClientError::new(
+            "Malformed authorization header",
+            ClientErrorKind::Unauthorized,
+        ))
+}
+
+/// Create an anonymous user in the project
+pub async fn create_anon_user(project: Uuid, pool: &PgPool) -> crate::Result<User> {
+    let user = sqlx::query_as::<_, User>(
+        "INSERT INTO users(is_anonymous,project) VALUES(true,$1) RETURNING *",
+    )
+    .bind(project)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Create a user with an email and password in the project, returns a
+/// [`ClientErrorKind::Conflict`] error if the email is taken. Registered
+/// users are players, owners are created with the project or by
+/// [`promote_owner`].
+pub async fn create_user(
+    project: Uuid,
+    email: &str,
+    password: &str,
+    pool: &PgPool,
+) -> crate::Result<User> {
+    let email = validate_email(email)?;
+    let hash = hash_password(password).await?;
+
+    insert_user(project, &email, &hash, Role::Player, pool).await
+}
+
+/// Insert a user with a validated email, a password hash and a role, see
+/// [`create_user`].
+pub(crate) async fn insert_user(
+    project: Uuid,
+    email: &str,
+    hash: &str,
+    role: Role,
+    executor: impl PgExecutor<'_>,
+) -> crate::Result<User> {
+    let result = sqlx::query_as::<_, User>(
+        "INSERT INTO users(email,encrypted_password,project,role) VALUES($1,$2,$3,$4) RETURNING *",
+    )
+    .bind(email)
+    .bind(hash)
+    .bind(project)
+    .bind(role)
+    .fetch_one(executor)
+    .await;
+
+    match result {
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+            let error = ClientError::new(
+                "A user with this email already exists",
+                ClientErrorKind::Conflict,
+            );
+            Err(error.into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Make the registered user with the email an owner of the project, used
+/// to set up the first owner of a project that has none. Returns `None` if
+/// there's no such user.
+pub async fn promote_owner(
+    project: Uuid,
+    email: &str,
+    pool: &PgPool,
+) -> crate::Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        "UPDATE users SET role = $3 
+        WHERE project = $1 AND email = $2 AND NOT is_anonymous 
+        RETURNING *",
+    )
+    .bind(project)
+    .bind(validate_email(email)?)
+    .bind(Role::Owner)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Log in to the project with an email and password, starting a new [`Session`].
+pub async fn login(
+    project: Uuid,
+    email: &str,
+    password: &str,
+    pool: &PgPool,
+) -> crate::Result<(User, Session)> {
+    let user = verify_credentials(project, email, password, pool).await?;
+    let session = Session::create(user.id, pool).await?;
+
+    Ok((user, session))
+}
+
+/// Get the user with the email and password, returns a
+/// [`ClientErrorKind::InvalidCredentials`] error if they don't match.
+async fn verify_credentials(
+    project: Uuid,
+    email: &str,
+    password: &str,
+    pool: &PgPool,
+) -> crate::Result<User> {
+    let user: Option<User> =
+        sqlx::query_as("SELECT * FROM users WHERE email = $1 AND project = $2")
+            .bind(email.trim().to_lowercase())
+            .bind(project)
+            .fetch_optional(pool)
+            .await?;
+
+    // Unknown emails are checked against a dummy hash so they take as
+    // long as a wrong password
+    let hash = user
+        .as_ref()
+        .and_then(|user| user.encrypted_password.as_deref());
+    let verified = verify_password(password, hash).await?;
+    match user {
+        Some(user) if verified => Ok(user),
+        _ => {
+            let error = ClientError::new(
+                "Invalid email or password",
+                ClientErrorKind::InvalidCredentials,
+            );
+            Err(error.into())
+        }
+    }
+}
+
+/// Turn an anonymous user into a full user by setting a password and an email
+/// or user name. The user keeps their id so all their leaderboard memberships and
+/// points stay attached.
+///
+/// Returns a [`ClientErrorKind::Conflict`] error if the email belongs to another
+/// user, in which case the anonymous user can be merged into that user with
+/// [`merge_anon_user`].
+pub async fn upgrade_anon_user(
+    user: &User,
+    email: Option<&str>,
+    user_name: Option<&str>,
+    password: &str,
+    pool: &PgPool,
+) -> crate::Result<User> {
+    if !user.is_anonymous {
+        let error = ClientError::new("User is already registered", ClientErrorKind::Conflict);
+        return Err(error.into());
+    }
+
+    let email = email.map(validate_email).transpose()?;
+    let user_name = user_name.map(str::trim).filter(|name| !name.is_empty());
+    if email.is_none() && user_name.is_none() {
+        let error = ClientError::new(
+            "An email or user name is required",
+            ClientErrorKind::InvalidInput,
+        );
+        return Err(error.into());
+    }
+
+    let hash = hash_password(password).await?;
+    let result = sqlx::query_as::<_, User>(
+        "UPDATE users 
+        SET email = $2, user_name = $3, encrypted_password = $4, is_anonymous = false 
+        WHERE id = $1 
+        RETURNING *",
+    )
+    .bind(user.id)
+    .bind(email)
+    .bind(user_name)
+    .bind(hash)
+    .fetch_one(pool)
+    .await;
+
+    match result {
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+            let error = ClientError::new(
+                "A user with this email already exists, log in to merge the accounts",
+                ClientErrorKind::Conflict,
+            );
+            Err(error.into())
+        }
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Merge an anonymous user into the existing user with the email and password
+/// in the same project.
+/// The leaderboard memberships, points and season results of the anonymous user
+/// are moved to the existing user and the anonymous user is deleted.
+pub async fn merge_anon_user(
+    user: &User,
+    email: &str,
+    password: &str,
+    pool: &PgPool,
+) -> crate::Result<User> {
+    if !user.is_anonymous {
+        let error = ClientError::new(
+            "Only anonymous users can be merged",
+            ClientErrorKind::Conflict,
+        );
+        return Err(error.into());
+    }
+
+    let target = verify_credentials(user.project, email, password, pool).await?;
+    let mut transaction = pool.begin().await?;
+
+    // Boards the target is already a member of keep the target's membership
+    sqlx::query(
+        "DELETE FROM leaderboard_members m 
+        WHERE m.player = $1 AND EXISTS (
+            SELECT 1 FROM leaderboard_members t 
+            WHERE t.player = $2 AND t.leaderboard = m.leaderboard
+        )",
+    )
+    .bind(user.id)
+    .bind(target.id)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query("UPDATE leaderboard_members SET player = $2 WHERE player = $1")
+        .bind(user.id)
+        .bind(target.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query("UPDATE points SET player = $2 WHERE player = $1")
+        .bind(user.id)
+        .bind(target.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    // Archived results are frozen, the target keeps their own result
+    // for seasons they both played in.
+    sqlx::query(
+        "UPDATE season_standings s SET player = $2 
+        WHERE s.player = $1 AND NOT EXISTS (
+            SELECT 1 FROM season_standings t WHERE t.player = $2 AND t.season = s.season
+        )",
+    )
+    .bind(user.id)
+    .bind(target.id)
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query("DELETE FROM season_standings WHERE player = $1")
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(target)
+}
+
+/// What an [`EmailToken`] can be used for
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "email_token_purpose", rename_all = "snake_case")]
+#[serde(rename_all = "camelCase")]
+pub enum TokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl TokenPurpose {
+    /// How long tokens are valid for
+    pub fn lifetime(&self) -> TimeDelta {
+        match self {
+            Self::VerifyEmail => TimeDelta::days(1),
+            Self::ResetPassword => TimeDelta::hours(1),
+        }
+    }
+}
+
+/// A single use token sent to a user's email address
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EmailToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub purpose: TokenPurpose,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl EmailToken {
+    /// Create a token for the user's email, returns the token and the
+    /// secret to send to the user.
+    pub async fn create(
+        user_id: Uuid,
+        email: &str,
+        purpose: TokenPurpose,
+        pool: &PgPool,
+    ) -> crate::Result<(Self, String)> {
+        let secret = token::generate(token::TOKEN_BYTES);
+        let token: EmailToken = sqlx::query_as(
+            "INSERT INTO email_tokens(user_id,email,purpose,token_hash,expires_at) 
+            VALUES($1,$2,$3,$4,$5) 
+            RETURNING *",
+        )
+        .bind(user_id)
+        .bind(email)
+        .bind(purpose)
+        .bind(token::hash(&secret))
+        .bind(Utc::now() + purpose.lifetime())
+        .fetch_one(pool)
+        .await?;
+
+        Ok((token, secret))
+    }
+
+    /// Mark an unused and unexpired token as used, returns an
+    /// [`ClientErrorKind::InvalidInput`] error if there's no such token.
+    pub async fn consume(
+        secret: &str,
+        purpose: TokenPurpose,
+        executor: impl PgExecutor<'_>,
+    ) -> crate::Result<Self> {
+        let token: Option<EmailToken> = sqlx::query_as(
+            "UPDATE email_tokens SET used_at = now() 
+            WHERE token_hash = $1 AND purpose = $2 AND used_at IS NULL AND expires_at > now() 
+            RETURNING *",
+        )
+        .bind(token::hash(secret))
+        .bind(purpose)
+        .fetch_optional(executor)
+        .await?;
+
+        let token = token.ok_or(ClientError::new(
+            "Invalid or expired token",
+            ClientErrorKind::InvalidInput,
+        ))?;
+
+        Ok(token)
+    }
+}
+
+/// Email a password reset token to the user with the email in the project. Nothing is
+/// sent if there's no such user so that emails can't be probed.
+pub async fn request_password_reset(
+    project: Uuid,
+    email: &str,
+    mailer: &dyn Mailer,
+    pool: &PgPool,
+) -> crate::Result<()> {
+    let email = validate_email(email)?;
+    let user: Option<User> = sqlx::query_as(
+        "SELECT * FROM users WHERE email = $1 AND project = $2 AND NOT is_anonymous",
+    )
+    .bind(&email)
+    .bind(project)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    let (_, secret) =
+        EmailToken::create(user.id, &email, TokenPurpose::ResetPassword, pool).await?;
+    let email = Email {
+        to: email,
+        subject: String::from("Reset your password"),
+        body: format!(
+            "Use this code to reset your password, it expires in an hour:\n\n{secret}\n\n\
+            If you didn't ask to reset your password you can ignore this email."
+        ),
+    };
+    mailer.send(email).await?;
+
+    Ok(())
+}
+
+/// Set a new password using a password reset token. All of the user's
+/// sessions and other reset tokens are revoked.
+pub async fn reset_password(secret: &str, password: &str, pool: &PgPool) -> crate::Result<User> {
+    let hash = hash_password(password).await?;
+    let mut transaction = pool.begin().await?;
+    let token = EmailToken::consume(secret, TokenPurpose::ResetPassword, &mut *transaction).await?;
+
+    // Receiving the token proves the user owns the email
+    let user: User = sqlx::query_as(
+        "UPDATE users 
+        SET encrypted_password = $2, email_verified_at = COALESCE(email_verified_at, now()) 
+        WHERE id = $1 
+        RETURNING *",
+    )
+    .bind(token.user_id)
+    .bind(hash)
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+        .bind(user.id)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query(
+        "UPDATE email_tokens SET used_at = now() 
+        WHERE user_id = $1 AND purpose = $2 AND used_at IS NULL",
+    )
+    .bind(user.id)
+    .bind(TokenPurpose::ResetPassword)
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    Ok(user)
+}
+
+/// Email a verification token to the user's email address
+pub async fn request_email_verification(
+    user: &User,
+    mailer: &dyn Mailer,
+    pool: &PgPool,
+) -> crate::Result<()> {
+    let Some(email) = &user.email else {
+        let error = ClientError::new("User doesn't have an email", ClientErrorKind::InvalidInput);
+        return Err(error.into());
+    };
+
+    if user.email_verified_at.is_some() {
+        let error = ClientError::new("Email is already verified", ClientErrorKind::Conflict);
+        return Err(error.into());
+    }
+
+    let (_, secret) = EmailToken::create(user.id, email, TokenPurpose::VerifyEmail, pool).await?;
+    let email = Email {
+        to: email.clone(),
+        subject: String::from("Verify your email"),
+        body: format!("Use this code to verify your email, it expires in a day:\n\n{secret}"),
+    };
+    mailer.send(email).await?;
+
+    Ok(())
+}
+
+/// Verify a user's email using a verification token
+pub async fn verify_email(secret: &str, pool: &PgPool) -> crate::Result<User> {
+    let mut transaction = pool.begin().await?;
+    let token = EmailToken::consume(secret, TokenPurpose::VerifyEmail, &mut *transaction).await?;
+
+    // The token is only valid for the address it was sent to
+    let user: Option<User> = sqlx::query_as(
+        "UPDATE users SET email_verified_at = COALESCE(email_verified_at, now()) 
+        WHERE id = $1 AND email = $2 
+        RETURNING *",
+    )
+    .bind(token.user_id)
+    .bind(&token.email)
+    .fetch_optional(&mut *transaction)
+    .await?;
+
+    let user = user.ok_or(ClientError::new(
+        "Invalid or expired token",
+        ClientErrorKind::InvalidInput,
+    ))?;
+    transaction.commit().await?;
+
+    Ok(user)
+}
+
+/// Set the role of a user in the project, returns `None` if the
+/// project has no such user.
+pub async fn set_role(
+    project: Uuid,
+    user_id: Uuid,
+    role: Role,
+    pool: &PgPool,
+) -> crate::Result<Option<User>> {
+    let user: Option<User> =
+        sqlx::query_as("UPDATE users SET role = $3 WHERE id = $1 AND project = $2 RETURNING *")
+            .bind(user_id)
+            .bind(project)
+            .bind(role)
+            .fetch_optional(pool)
+            .await?;
+
     Ok(user)
 }
 
-/// Create a random url safe string of length `n` bytes.
-pub fn gen_random_string(n: usize) -> String {
-    let bytes: Vec<u8> = vec![rand::random(); n];
-    URL_SAFE.encode(bytes)
+/// Hash a password using argon2 on a blocking thread, returns an error
+/// if the password is shorter than [`MIN_PASSWORD_LENGTH`].
+pub async fn hash_password(password: &str) -> crate::Result<String> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        let message = format!("Passwords must be at least {MIN_PASSWORD_LENGTH} characters long");
+        return Err(ClientError::new(&message, ClientErrorKind::InvalidInput).into());
+    }
+
+    let password = password.to_owned();
+    let hash = tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    })
+    .await??;
+
+    Ok(hash)
+}
+
+/// Check if a password matches an argon2 hash on a blocking thread. A
+/// missing hash never matches but still takes as long to check, so
+/// callers don't reveal which users exist.
+pub async fn verify_password(password: &str, hash: Option<&str>) -> crate::Result<bool> {
+    let password = password.to_owned();
+    let hash = hash.map(str::to_owned);
+    let matches = tokio::task::spawn_blocking(move || {
+        let matches = check_password(&password, hash.as_deref().unwrap_or(&DUMMY_HASH));
+        matches && hash.is_some()
+    })
+    .await?;
+
+    Ok(matches)
+}
+
+/// A hash of a random password to check against when there's no hash
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(salt.as_str().as_bytes(), &salt)
+        .expect("Failed to hash the dummy password")
+        .to_string()
+});
+
+fn check_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Normalize an email address, returns an error if it's not a valid
+/// email address.
+pub(crate) fn validate_email(email: &str) -> Result<String, ClientError> {
+    let email = email.trim().to_lowercase();
+    match email.split_once('@') {
+        Some((name, domain)) if !name.is_empty() && domain.contains('.') => Ok(email),
+        _ => Err(ClientError::new(
+            "Invalid email address",
+            ClientErrorKind::InvalidInput,
+        )),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::board::Leaderboard;
+    use crate::mail::MemoryMailer;
+    use crate::project::DEFAULT_PROJECT;
+    use rust_decimal::Decimal;
 
     #[sqlx::test(migrations = "./migrations")]
     async fn new_anon_user(pool: PgPool) -> crate::Result<()> {
-        let user = create_anon_user(&pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
         assert!(user.is_anonymous);
 
         Ok(())
     }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn sign_up_and_login(pool: PgPool) -> crate::Result<()> {
+        let user = create_user(DEFAULT_PROJECT, "Player@Example.com", "hunter22", &pool).await?;
+        assert!(!user.is_anonymous);
+        assert_eq!(user.email.as_deref(), Some("player@example.com"));
+        assert_ne!(user.encrypted_password.as_deref(), Some("hunter22"));
+
+        let (logged_in, session) =
+            login(DEFAULT_PROJECT, "player@example.com", "hunter22", &pool).await?;
+        assert_eq!(logged_in.id, user.id);
+        assert_eq!(session.user_id, user.id);
+
+        let result = login(DEFAULT_PROJECT, "player@example.com", "hunter23", &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::InvalidCredentials
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn promote_an_owner(pool: PgPool) -> crate::Result<()> {
+        let user = create_user(DEFAULT_PROJECT, "owner@example.com", "hunter22", &pool).await?;
+        assert_eq!(user.role, Role::Player);
+
+        let owner = promote_owner(DEFAULT_PROJECT, "Owner@Example.com", &pool).await?;
+        assert_eq!(
+            owner.map(|owner| (owner.id, owner.role)),
+            Some((user.id, Role::Owner))
+        );
+        assert!(
+            promote_owner(DEFAULT_PROJECT, "missing@example.com", &pool)
+                .await?
+                .is_none()
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn duplicate_email(pool: PgPool) -> crate::Result<()> {
+        create_user(DEFAULT_PROJECT, "player@example.com", "password", &pool).await?;
+        let result = create_user(DEFAULT_PROJECT, "player@example.com", "password", &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::Conflict
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn expired_sessions(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let session = Session::create(user.id, &pool).await?;
+
+        let (_, session_user) = Session::get_user(&session.token, &pool).await?.unwrap();
+        assert_eq!(session_user.id, user.id);
+
+        sqlx::query("UPDATE sessions SET expires_at = now() WHERE id = $1")
+            .bind(session.id)
+            .execute(&pool)
+            .await?;
+        assert!(Session::get_user(&session.token, &pool).await?.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn upgrade_keeps_history(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "Board", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board.submit_score(user.id, Decimal::TEN, &pool).await?;
+
+        let upgraded = upgrade_anon_user(&user, None, Some("player"), "hunter22", &pool).await?;
+        assert_eq!(upgraded.id, user.id);
+        assert_eq!(upgraded.user_name.as_deref(), Some("player"));
+        assert!(!upgraded.is_anonymous);
+        assert_eq!(upgraded.role, Role::Player);
+
+        let standings = board
+            .standings(&Default::default(), &Default::default(), &pool)
+            .await?;
+        assert_eq!(standings[0].player, user.id);
+
+        let result = upgrade_anon_user(&upgraded, None, Some("player"), "hunter22", &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::Conflict
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn merge_moves_history(pool: PgPool) -> crate::Result<()> {
+        let target = create_user(DEFAULT_PROJECT, "player@example.com", "hunter22", &pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+
+        let board = Leaderboard::new(DEFAULT_PROJECT, "Board", &pool).await?;
+        let shared = Leaderboard::new(DEFAULT_PROJECT, "Shared board", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        shared.add_member(user.id, &pool).await?;
+        shared.add_member(target.id, &pool).await?;
+        board.submit_score(user.id, Decimal::TEN, &pool).await?;
+        shared.submit_score(user.id, Decimal::ONE, &pool).await?;
+        shared.submit_score(target.id, Decimal::TWO, &pool).await?;
+
+        let merged = merge_anon_user(&user, "player@example.com", "hunter22", &pool).await?;
+        assert_eq!(merged.id, target.id);
+
+        assert!(board.get_member(target.id, &pool).await?.is_some());
+        assert_eq!(shared.get_members(&pool).await?.len(), 1);
+
+        let standings = shared
+            .standings(&Default::default(), &Default::default(), &pool)
+            .await?;
+        assert_eq!(standings.len(), 1);
+        assert_eq!(standings[0].score, Decimal::from(3));
+
+        let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(users, 1);
+
+        Ok(())
+    }
+
+    /// Get the token from the last email sent
+    fn sent_token(mailer: &MemoryMailer) -> String {
+        let email = mailer.sent().pop().unwrap();
+        email.body.lines().nth(2).unwrap().to_owned()
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn reset_a_password(pool: PgPool) -> crate::Result<()> {
+        let mailer = MemoryMailer::default();
+        let user = create_user(DEFAULT_PROJECT, "player@example.com", "hunter22", &pool).await?;
+        let session = Session::create(user.id, &pool).await?;
+
+        request_password_reset(DEFAULT_PROJECT, "nobody@example.com", &mailer, &pool).await?;
+        assert!(mailer.sent().is_empty());
+
+        request_password_reset(DEFAULT_PROJECT, "Player@example.com", &mailer, &pool).await?;
+        let token = sent_token(&mailer);
+
+        let user = reset_password(&token, "hunter23", &pool).await?;
+        assert!(user.email_verified_at.is_some());
+        assert!(Session::get_user(&session.token, &pool).await?.is_none());
+        login(DEFAULT_PROJECT, "player@example.com", "hunter23", &pool).await?;
+
+        // Tokens can only be used once
+        let result = reset_password(&token, "hunter24", &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::InvalidInput
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn expired_reset_tokens(pool: PgPool) -> crate::Result<()> {
+        let mailer = MemoryMailer::default();
+        create_user(DEFAULT_PROJECT, "player@example.com", "hunter22", &pool).await?;
+        request_password_reset(DEFAULT_PROJECT, "player@example.com", &mailer, &pool).await?;
+
+        sqlx::query("UPDATE email_tokens SET expires_at = now()")
+            .execute(&pool)
+            .await?;
+        let result = reset_password(&sent_token(&mailer), "hunter23", &pool).await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn verify_an_email(pool: PgPool) -> crate::Result<()> {
+        let mailer = MemoryMailer::default();
+        let user = create_user(DEFAULT_PROJECT, "player@example.com", "hunter22", &pool).await?;
+        assert!(user.email_verified_at.is_none());
+
+        request_email_verification(&user, &mailer, &pool).await?;
+        assert_eq!(mailer.sent()[0].to, "player@example.com");
+
+        let token = sent_token(&mailer);
+        assert!(reset_password(&token, "hunter23", &pool).await.is_err());
+
+        let user = verify_email(&token, &pool).await?;
+        assert!(user.email_verified_at.is_some());
+
+        let result = request_email_verification(&user, &mailer, &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::Conflict
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn short_passwords_are_rejected() {
+        assert!(hash_password("short").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_hashes_never_match() -> crate::Result<()> {
+        let hash = hash_password("password123").await?;
+        assert!(verify_password("password123", Some(&hash)).await?);
+        assert!(!verify_password("password124", Some(&hash)).await?);
+        assert!(!verify_password("password123", None).await?);
+
+        Ok(())
+    }
 }
@@ -1,3 +1,6 @@
+use crate::{ClientError, ClientErrorKind, score, season::Season, window::TimeWindow};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, prelude::FromRow};
 use uuid::Uuid;
@@ -6,6 +9,72 @@ use uuid::Uuid;
 pub struct Leaderboard {
     pub id: i32,
     pub name: String,
+    pub aggregation: Aggregation,
+    pub sort_order: SortOrder,
+    pub project: Uuid,
+}
+
+/// How the points of a player are combined into a single score
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "score_aggregation", rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
+pub enum Aggregation {
+    /// The sum of all the points
+    #[default]
+    Sum,
+    /// The highest point, or the lowest point on ascending boards
+    Best,
+    /// The most recently submitted point
+    Latest,
+    /// The mean of all the points
+    Average,
+    /// The number of points submitted
+    Count,
+}
+
+impl Aggregation {
+    /// The postgres aggregate expression over the `points` table
+    fn sql_expression(&self, order: SortOrder) -> &'static str {
+        match self {
+            Self::Sum => "SUM(value)",
+            Self::Best => match order {
+                SortOrder::Descending => "MAX(value)",
+                SortOrder::Ascending => "MIN(value)",
+            },
+            Self::Latest => "(ARRAY_AGG(value ORDER BY created_at DESC, id DESC))[1]",
+            Self::Average => "AVG(value)",
+            Self::Count => "COUNT(*)",
+        }
+    }
+}
+
+/// The direction players are ranked in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "sort_order", rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
+pub enum SortOrder {
+    /// Higher scores are ranked first
+    #[default]
+    Descending,
+    /// Lower scores are ranked first, for example time based scores
+    Ascending,
+}
+
+impl SortOrder {
+    fn sql_keyword(&self) -> &'static str {
+        match self {
+            Self::Descending => "DESC",
+            Self::Ascending => "ASC",
+        }
+    }
+}
+
+/// Settings used when creating a [`Leaderboard`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BoardOptions {
+    pub aggregation: Aggregation,
+    pub sort_order: SortOrder,
 }
 
 #[derive(Debug, FromRow, Serialize, Deserialize)]
@@ -16,50 +85,278 @@ pub struct LeaderboardMember {
     pub player: Uuid,
 }
 
+/// A single score submitted by a player to a leaderboard
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Point {
+    pub id: i32,
+    pub leaderboard: i32,
+    pub value: Decimal,
+    pub player: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub season: Option<i32>,
+}
+
+/// How players with equal scores are ranked
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum RankMode {
+    /// Equal scores share a rank and the next rank is skipped, i.e. `1,1,3`.
+    #[default]
+    Competition,
+    /// Equal scores share a rank and no ranks are skipped, i.e. `1,1,2`.
+    Dense,
+    /// Every player gets a unique rank, ties go to the least recently
+    /// updated player, i.e. the one whose latest point is oldest, `1,2,3`.
+    Ordinal,
+}
+
+impl RankMode {
+    /// The postgres window function used to compute the rank
+    fn window_function(&self, order: SortOrder) -> String {
+        let order = order.sql_keyword();
+        match self {
+            Self::Competition => format!("RANK() OVER (ORDER BY s.score {order})"),
+            Self::Dense => format!("DENSE_RANK() OVER (ORDER BY s.score {order})"),
+            Self::Ordinal => {
+                format!("ROW_NUMBER() OVER (ORDER BY s.score {order}, s.updated_at, s.player)")
+            }
+        }
+    }
+}
+
+/// Options for querying the standings of a leaderboard
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StandingsQuery {
+    pub mode: RankMode,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl StandingsQuery {
+    /// The maximum number of standings returned at once
+    pub const MAX_LIMIT: i64 = 1000;
+}
+
+impl Default for StandingsQuery {
+    fn default() -> Self {
+        Self {
+            mode: RankMode::default(),
+            limit: 100,
+            offset: 0,
+        }
+    }
+}
+
+/// Options for querying the standings around a player
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AroundQuery {
+    pub mode: RankMode,
+    /// The number of neighbours on each side of the player
+    pub count: i64,
+}
+
+impl AroundQuery {
+    /// The maximum number of neighbours on each side of the player
+    pub const MAX_COUNT: i64 = 50;
+}
+
+impl Default for AroundQuery {
+    fn default() -> Self {
+        Self {
+            mode: RankMode::default(),
+            count: 5,
+        }
+    }
+}
+
+/// A player's position on a leaderboard
+#[derive(Debug, FromRow, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Standing {
+    pub rank: i64,
+    pub player: Uuid,
+    pub player_alias: Option<String>,
+    pub score: Decimal,
+}
+
+/// A player's standing after a submission and their rank before it,
+/// see [`Leaderboard::standing_change`]
+#[derive(Debug, FromRow)]
+pub struct StandingChange {
+    #[sqlx(flatten)]
+    pub standing: Standing,
+    pub previous_rank: Option<i64>,
+}
+
+/// A player's rank along with the players directly above
+/// and below them.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerWindow {
+    pub rank: i64,
+    pub standings: Vec<Standing>,
+}
+
 impl Leaderboard {
     /// Create a new [`Leaderboard`]
     ///
     /// # Example
     /// ```
-    /// use scoreboard::{board::Leaderboard,Error,AppState};
+    /// use scoreboard::{board::Leaderboard,Error,project::DEFAULT_PROJECT};
+    /// use sqlx::PgPool;
     ///
     /// #[tokio::main]
     /// async fn main() -> Result<(),Error>{
     ///     dotenv::dotenv();
     ///
-    ///     let state = AppState::new().await?;
-    ///     let board = Leaderboard::new("My leaderboard",state.pool())
+    ///     let pool = PgPool::connect(&std::env::var("DATABASE_URL").unwrap()).await?;
+    ///     let board = Leaderboard::new(DEFAULT_PROJECT,"My leaderboard",&pool)
     ///         .await?;
     ///
     ///     Ok(())
     /// }
     /// ```
-    pub async fn new(name: &str, pool: &PgPool) -> crate::Result<Self> {
-        let leaderboard: Leaderboard =
-            sqlx::query_as("INSERT INTO leaderboards(name) VALUES($1) RETURNING *")
-                .bind(name)
-                .fetch_one(pool)
+    pub async fn new(project: Uuid, name: &str, pool: &PgPool) -> crate::Result<Self> {
+        Self::with_options(project, name, &BoardOptions::default(), pool).await
+    }
+
+    /// Create a new [`Leaderboard`] with the given [`BoardOptions`]
+    pub async fn with_options(
+        project: Uuid,
+        name: &str,
+        options: &BoardOptions,
+        pool: &PgPool,
+    ) -> crate::Result<Self> {
+        let leaderboard: Leaderboard = sqlx::query_as(
+            "INSERT INTO leaderboards(name,aggregation,sort_order,project) 
+            VALUES($1,$2,$3,$4) 
+            RETURNING *",
+        )
+        .bind(name)
+        .bind(options.aggregation)
+        .bind(options.sort_order)
+        .bind(project)
+        .fetch_one(pool)
+        .await?;
+
+        Ok(leaderboard)
+    }
+
+    /// Get a [`Leaderboard`] in the project by it's id
+    pub async fn get(project: Uuid, id: i32, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let leaderboard: Option<Leaderboard> =
+            sqlx::query_as("SELECT * FROM leaderboards WHERE id = $1 AND project = $2")
+                .bind(id)
+                .bind(project)
+                .fetch_optional(pool)
                 .await?;
 
         Ok(leaderboard)
     }
 
-    /// Add a player to the board members
+    /// Get all the leaderboards in the project
+    pub async fn all(project: Uuid, pool: &PgPool) -> crate::Result<Vec<Self>> {
+        let leaderboards: Vec<Leaderboard> =
+            sqlx::query_as("SELECT * FROM leaderboards WHERE project = $1 ORDER BY id")
+                .bind(project)
+                .fetch_all(pool)
+                .await?;
+
+        Ok(leaderboards)
+    }
+
+    /// Rename the board
+    pub async fn rename(&mut self, name: &str, pool: &PgPool) -> crate::Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            let error = ClientError::new("Name cannot be empty", ClientErrorKind::InvalidInput);
+            return Err(error.into());
+        }
+
+        sqlx::query("UPDATE leaderboards SET name = $2 WHERE id = $1")
+            .bind(self.id)
+            .bind(name)
+            .execute(pool)
+            .await?;
+        self.name = name.to_owned();
+
+        Ok(())
+    }
+
+    /// Delete the board along with its members, points and seasons
+    pub async fn delete(self, pool: &PgPool) -> crate::Result<()> {
+        let mut transaction = pool.begin().await?;
+
+        let statements = [
+            "DELETE FROM season_standings WHERE season IN (SELECT id FROM seasons WHERE leaderboard = $1)",
+            "DELETE FROM points WHERE leaderboard = $1",
+            "DELETE FROM seasons WHERE leaderboard = $1",
+            "DELETE FROM season_schedules WHERE leaderboard = $1",
+            "DELETE FROM leaderboard_members WHERE leaderboard = $1",
+            "DELETE FROM leaderboards WHERE id = $1",
+        ];
+        for statement in statements {
+            sqlx::query(statement)
+                .bind(self.id)
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        transaction.commit().await?;
+
+        Ok(())
+    }
+
+    /// Add a player to the board members, the player must be in the
+    /// same project as the board. Returns a [`ClientErrorKind::Conflict`]
+    /// error if the player is already a member.
     pub async fn add_member(&self, player_id: Uuid, pool: &PgPool) -> crate::Result<()> {
-        sqlx::query(
+        let result = sqlx::query(
             "INSERT INTO leaderboard_members(player,leaderboard) 
-            VALUES($1,$2) 
-            RETURNING *",
+            SELECT id,$2 FROM users WHERE id = $1 AND project = $3",
         )
         .bind(player_id)
         .bind(self.id)
+        .bind(self.project)
         .execute(pool)
-        .await?;
+        .await;
+
+        let result = match result {
+            Ok(result) => result,
+            Err(sqlx::Error::Database(error)) if error.is_unique_violation() => {
+                let error = ClientError::new(
+                    "Player is already a member of this leaderboard",
+                    ClientErrorKind::Conflict,
+                );
+                return Err(error.into());
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        if result.rows_affected() == 0 {
+            return Err(ClientError::not_found("Player not found").into());
+        }
 
         Ok(())
     }
 
-    /// Add a player to the board members
+    /// Remove a player from the board members, the player's points are
+    /// kept but they no longer appear in the standings. Returns `false` if
+    /// the player wasn't a member.
+    pub async fn remove_member(&self, player_id: Uuid, pool: &PgPool) -> crate::Result<bool> {
+        let result =
+            sqlx::query("DELETE FROM leaderboard_members WHERE leaderboard = $1 AND player = $2")
+                .bind(self.id)
+                .bind(player_id)
+                .execute(pool)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get all the board members
     pub async fn get_members(&self, pool: &PgPool) -> crate::Result<Vec<LeaderboardMember>> {
         let members: Vec<LeaderboardMember> =
             sqlx::query_as("SELECT * FROM leaderboard_members WHERE leaderboard = $1")
@@ -69,17 +366,211 @@ impl Leaderboard {
 
         Ok(members)
     }
+
+    /// Get a single member of the board, returns `None` if the
+    /// player is not a member.
+    pub async fn get_member(
+        &self,
+        player_id: Uuid,
+        pool: &PgPool,
+    ) -> crate::Result<Option<LeaderboardMember>> {
+        let member: Option<LeaderboardMember> = sqlx::query_as(
+            "SELECT * FROM leaderboard_members WHERE leaderboard = $1 AND player = $2",
+        )
+        .bind(self.id)
+        .bind(player_id)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Submit a score for a player, the player must be a member
+    /// of the board. The score is rounded to [`score::SCALE`] decimal
+    /// places and attributed to the current [`Season`] if the board
+    /// has one.
+    pub async fn submit_score(
+        &self,
+        player_id: Uuid,
+        value: Decimal,
+        pool: &PgPool,
+    ) -> crate::Result<Point> {
+        let value = score::normalize(value)?;
+        if self.get_member(player_id, pool).await?.is_none() {
+            let error = ClientError::new(
+                "Player is not a member of this leaderboard",
+                ClientErrorKind::Forbidden,
+            );
+            return Err(error.into());
+        }
+
+        let season = Season::current(self, pool).await?;
+        let point: Point = sqlx::query_as(
+            "INSERT INTO points(leaderboard,value,player,season) 
+            VALUES($1,$2,$3,$4) 
+            RETURNING *",
+        )
+        .bind(self.id)
+        .bind(value)
+        .bind(player_id)
+        .bind(season.map(|season| season.id))
+        .fetch_one(pool)
+        .await?;
+
+        Ok(point)
+    }
+
+    /// Get the ranked standings of the board, the points of each player
+    /// are combined using the board's [`Aggregation`] and players are
+    /// ranked in the board's [`SortOrder`]. Only points submitted within
+    /// the [`TimeWindow`] are counted, boards with seasons only count the
+    /// points of the current season.
+    pub async fn standings(
+        &self,
+        query: &StandingsQuery,
+        window: &TimeWindow,
+        pool: &PgPool,
+    ) -> crate::Result<Vec<Standing>> {
+        let sql = format!(
+            "{} SELECT rank,player,player_alias,score FROM ranked 
+            ORDER BY position 
+            LIMIT $4 OFFSET $5",
+            self.ranked_sql(query.mode)
+        );
+
+        let season = Season::current(self, pool).await?;
+        let standings: Vec<Standing> = sqlx::query_as(&sql)
+            .bind(self.id)
+            .bind(window.start(Utc::now()))
+            .bind(season.map(|season| season.id))
+            .bind(query.limit.clamp(0, StandingsQuery::MAX_LIMIT))
+            .bind(query.offset.max(0))
+            .fetch_all(pool)
+            .await?;
+
+        Ok(standings)
+    }
+
+    /// Get the standings around a player, returns `None` if the
+    /// player has no score on the board within the [`TimeWindow`].
+    pub async fn around_player(
+        &self,
+        player_id: Uuid,
+        query: &AroundQuery,
+        window: &TimeWindow,
+        pool: &PgPool,
+    ) -> crate::Result<Option<PlayerWindow>> {
+        let sql = format!(
+            "{}, target AS (
+                SELECT position FROM ranked WHERE player = $4
+            )
+            SELECT r.rank,r.player,r.player_alias,r.score FROM ranked r, target t
+            WHERE r.position BETWEEN t.position - $5 AND t.position + $5
+            ORDER BY r.position",
+            self.ranked_sql(query.mode)
+        );
+
+        let season = Season::current(self, pool).await?;
+        let standings: Vec<Standing> = sqlx::query_as(&sql)
+            .bind(self.id)
+            .bind(window.start(Utc::now()))
+            .bind(season.map(|season| season.id))
+            .bind(player_id)
+            .bind(query.count.clamp(0, AroundQuery::MAX_COUNT))
+            .fetch_all(pool)
+            .await?;
+
+        let window = standings
+            .iter()
+            .find(|standing| standing.player == player_id)
+            .map(|standing| standing.rank)
+            .map(|rank| PlayerWindow { rank, standings });
+
+        Ok(window)
+    }
+
+    /// Get how a point moved its player by comparing the player's
+    /// standing with and without the point in one query, returns `None`
+    /// if the player has no standing.
+    pub async fn standing_change(
+        &self,
+        point: &Point,
+        pool: &PgPool,
+    ) -> crate::Result<Option<StandingChange>> {
+        let mode = RankMode::default();
+        let sql = format!(
+            "WITH {}, {}
+            SELECT r.rank,r.player,r.player_alias,r.score,p.rank AS previous_rank 
+            FROM ranked r 
+            LEFT JOIN previous p ON p.player = r.player 
+            WHERE r.player = $4",
+            self.ranked_ctes(mode, "ranked", ""),
+            self.ranked_ctes(mode, "previous", "AND id <> $5"),
+        );
+
+        let change: Option<StandingChange> = sqlx::query_as(&sql)
+            .bind(self.id)
+            .bind(None::<DateTime<Utc>>)
+            .bind(point.season)
+            .bind(point.player)
+            .bind(point.id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(change)
+    }
+
+    /// Builds a `ranked` common table expression containing the rank and
+    /// position of every player on the board bound to `$1`, counting the
+    /// points created after `$2` and in the season `$3` if they're not null.
+    pub(crate) fn ranked_sql(&self, mode: RankMode) -> String {
+        format!("WITH {}", self.ranked_ctes(mode, "ranked", ""))
+    }
+
+    /// Builds the `{name}_scores` and `{name}` expressions of
+    /// [`Self::ranked_sql`], only counting the points matching `filter`.
+    fn ranked_ctes(&self, mode: RankMode, name: &str, filter: &str) -> String {
+        format!(
+            "{name}_scores AS (
+            SELECT player, {score} AS score, MAX(created_at) AS updated_at
+            FROM points 
+            WHERE leaderboard = $1 
+                AND player IN (SELECT player FROM leaderboard_members WHERE leaderboard = $1)
+                AND ($2::TIMESTAMPTZ IS NULL OR created_at >= $2)
+                AND ($3::INTEGER IS NULL OR season = $3) 
+                {filter}
+            GROUP BY player
+        ), {name} AS (
+            SELECT 
+                {rank} AS rank,
+                ROW_NUMBER() OVER (ORDER BY s.score {order}, s.updated_at, s.player) AS position,
+                s.player,
+                (
+                    SELECT player_alias FROM leaderboard_members m 
+                    WHERE m.leaderboard = $1 AND m.player = s.player 
+                    LIMIT 1
+                ) AS player_alias,
+                ROUND(s.score::NUMERIC, 4) AS score
+            FROM {name}_scores s
+        )",
+            score = self.aggregation.sql_expression(self.sort_order),
+            rank = mode.window_function(self.sort_order),
+            order = self.sort_order.sql_keyword()
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::auth::create_anon_user;
+    use crate::project::DEFAULT_PROJECT;
+    use crate::window::Period;
 
     #[sqlx::test(migrations = "./migrations")]
     async fn add_player_to_board(pool: PgPool) -> crate::Result<()> {
-        let user = create_anon_user(&pool).await?;
-        let board = Leaderboard::new("My leaderboard", &pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
         board.add_member(user.id, &pool).await?;
 
         let member: LeaderboardMember =
@@ -93,13 +584,76 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(migrations = "./migrations")]
+    async fn add_player_to_board_twice(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+
+        let result = board.add_member(user.id, &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::Conflict
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn removed_members_keep_their_points(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board.submit_score(user.id, Decimal::TEN, &pool).await?;
+
+        assert!(board.remove_member(user.id, &pool).await?);
+        assert!(!board.remove_member(user.id, &pool).await?);
+        let standings = board
+            .standings(&StandingsQuery::default(), &TimeWindow::default(), &pool)
+            .await?;
+        assert!(standings.is_empty());
+
+        board.add_member(user.id, &pool).await?;
+        let standings = board
+            .standings(&StandingsQuery::default(), &TimeWindow::default(), &pool)
+            .await?;
+        assert_eq!(standings[0].score, Decimal::TEN);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn compare_standings_before_a_point(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let user2 = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board.add_member(user2.id, &pool).await?;
+
+        let point = board.submit_score(user.id, Decimal::TEN, &pool).await?;
+        let change = board.standing_change(&point, &pool).await?.unwrap();
+        assert_eq!((change.previous_rank, change.standing.rank), (None, 1));
+
+        board
+            .submit_score(user2.id, Decimal::from(20), &pool)
+            .await?;
+        let point = board
+            .submit_score(user.id, Decimal::from(30), &pool)
+            .await?;
+        let change = board.standing_change(&point, &pool).await?.unwrap();
+        assert_eq!((change.previous_rank, change.standing.rank), (Some(2), 1));
+        assert_eq!(change.standing.score, Decimal::from(40));
+
+        Ok(())
+    }
+
     #[sqlx::test(migrations = "./migrations")]
     async fn get_board_members(pool: PgPool) -> crate::Result<()> {
-        let user = create_anon_user(&pool).await?;
-        let user2 = create_anon_user(&pool).await?;
-        let user3 = create_anon_user(&pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let user2 = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let user3 = create_anon_user(DEFAULT_PROJECT, &pool).await?;
 
-        let board = Leaderboard::new("My leaderboard", &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
         board.add_member(user.id, &pool).await?;
         board.add_member(user2.id, &pool).await?;
         board.add_member(user3.id, &pool).await?;
@@ -110,4 +664,308 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn submit_member_score(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+
+        let point = board
+            .submit_score(user.id, Decimal::new(245, 1), &pool)
+            .await?;
+        assert_eq!(point.value, Decimal::new(245, 1));
+        assert_eq!(point.player, user.id);
+        assert_eq!(point.leaderboard, board.id);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn non_members_cannot_submit_scores(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+
+        let result = board.submit_score(user.id, Decimal::TEN, &pool).await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::Forbidden
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn submit_fractional_and_negative_scores(pool: PgPool) -> crate::Result<()> {
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        board.add_member(user.id, &pool).await?;
+
+        let point = board
+            .submit_score(user.id, Decimal::new(1234565, 5), &pool)
+            .await?;
+        assert_eq!(point.value, Decimal::new(123457, 4));
+
+        board
+            .submit_score(user.id, Decimal::new(-5, 1), &pool)
+            .await?;
+        let standings = board
+            .standings(&StandingsQuery::default(), &TimeWindow::default(), &pool)
+            .await?;
+        assert_eq!(standings[0].score, Decimal::new(118457, 4));
+
+        // The largest score fits the column
+        let point = board.submit_score(user.id, score::MAX, &pool).await?;
+        assert_eq!(point.value, score::MAX);
+
+        let result = board
+            .submit_score(user.id, Decimal::from(1_000_000), &pool)
+            .await;
+        assert!(matches!(
+            result,
+            Err(crate::Error::ClientError(error)) if error.kind() == ClientErrorKind::InvalidScore
+        ));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn rank_ties(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        let mut players = vec![];
+        for _ in 0..3 {
+            let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+            board.add_member(user.id, &pool).await?;
+            players.push(user.id);
+        }
+
+        board
+            .submit_score(players[0], Decimal::from(10), &pool)
+            .await?;
+        board
+            .submit_score(players[1], Decimal::from(4), &pool)
+            .await?;
+        board
+            .submit_score(players[1], Decimal::from(6), &pool)
+            .await?;
+        board
+            .submit_score(players[2], Decimal::from(5), &pool)
+            .await?;
+
+        let modes = [
+            (RankMode::Competition, [1, 1, 3]),
+            (RankMode::Dense, [1, 1, 2]),
+            (RankMode::Ordinal, [1, 2, 3]),
+        ];
+
+        for (mode, ranks) in modes {
+            let query = StandingsQuery {
+                mode,
+                ..Default::default()
+            };
+            let standings = board
+                .standings(&query, &TimeWindow::default(), &pool)
+                .await?;
+            let result: Vec<i64> = standings.iter().map(|s| s.rank).collect();
+            assert_eq!(result, ranks);
+
+            let order: Vec<Uuid> = standings.iter().map(|s| s.player).collect();
+            assert_eq!(order, players);
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn aggregate_scores(pool: PgPool) -> crate::Result<()> {
+        let aggregations = [
+            (Aggregation::Sum, 30),
+            (Aggregation::Best, 15),
+            (Aggregation::Latest, 5),
+            (Aggregation::Average, 10),
+            (Aggregation::Count, 3),
+        ];
+
+        for (aggregation, score) in aggregations {
+            let options = BoardOptions {
+                aggregation,
+                ..Default::default()
+            };
+            let board =
+                Leaderboard::with_options(DEFAULT_PROJECT, "My leaderboard", &options, &pool)
+                    .await?;
+            let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+            board.add_member(user.id, &pool).await?;
+
+            for value in [10, 15, 5] {
+                board.submit_score(user.id, value.into(), &pool).await?;
+            }
+
+            let standings = board
+                .standings(&StandingsQuery::default(), &TimeWindow::default(), &pool)
+                .await?;
+            assert_eq!(standings[0].score, score.into(), "{aggregation:?}");
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn rank_ascending_board(pool: PgPool) -> crate::Result<()> {
+        let options = BoardOptions {
+            aggregation: Aggregation::Best,
+            sort_order: SortOrder::Ascending,
+        };
+        let board = Leaderboard::with_options(DEFAULT_PROJECT, "Speedrun", &options, &pool).await?;
+        let mut players = vec![];
+        let times = [[9550, 8025], [8200, 12000], [6000, 20000]];
+        for times in times {
+            let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+            board.add_member(user.id, &pool).await?;
+            for time in times {
+                let time = Decimal::new(time, 2);
+                board.submit_score(user.id, time, &pool).await?;
+            }
+            players.push(user.id);
+        }
+
+        let query = StandingsQuery {
+            limit: 2,
+            ..Default::default()
+        };
+        let standings = board
+            .standings(&query, &TimeWindow::default(), &pool)
+            .await?;
+        let top: Vec<(Uuid, Decimal)> = standings.iter().map(|s| (s.player, s.score)).collect();
+        let expected = [
+            (players[2], Decimal::from(60)),
+            (players[0], Decimal::new(8025, 2)),
+        ];
+        assert_eq!(top, expected);
+
+        let window = board
+            .around_player(
+                players[1],
+                &AroundQuery::default(),
+                &TimeWindow::default(),
+                &pool,
+            )
+            .await?
+            .unwrap();
+        assert_eq!(window.rank, 3);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn standings_within_window(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let user2 = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board.add_member(user2.id, &pool).await?;
+
+        sqlx::query(
+            "INSERT INTO points(leaderboard,value,player,created_at) 
+            VALUES($1,100,$2,now() - INTERVAL '10 days')",
+        )
+        .bind(board.id)
+        .bind(user.id)
+        .execute(&pool)
+        .await?;
+        board.submit_score(user.id, Decimal::from(5), &pool).await?;
+        board
+            .submit_score(user2.id, Decimal::from(20), &pool)
+            .await?;
+
+        let query = StandingsQuery::default();
+        let standings = board
+            .standings(&query, &TimeWindow::default(), &pool)
+            .await?;
+        assert_eq!(standings[0].player, user.id);
+        assert_eq!(standings[0].score, Decimal::from(105));
+
+        let windows = [
+            TimeWindow::rolling(Period::Week),
+            TimeWindow::calendar(Period::Day),
+        ];
+        for window in windows {
+            let standings = board.standings(&query, &window, &pool).await?;
+            let scores: Vec<(Uuid, Decimal)> =
+                standings.iter().map(|s| (s.player, s.score)).collect();
+            assert_eq!(
+                scores,
+                [(user2.id, Decimal::from(20)), (user.id, Decimal::from(5))]
+            );
+        }
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn paginate_standings(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        let mut players = vec![];
+        for score in [30, 20, 10] {
+            let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+            board.add_member(user.id, &pool).await?;
+            board.submit_score(user.id, score.into(), &pool).await?;
+            players.push(user.id);
+        }
+
+        let query = StandingsQuery {
+            limit: 1,
+            offset: 1,
+            ..Default::default()
+        };
+        let standings = board
+            .standings(&query, &TimeWindow::default(), &pool)
+            .await?;
+
+        assert_eq!(standings.len(), 1);
+        assert_eq!(standings[0].player, players[1]);
+        assert_eq!(standings[0].rank, 2);
+        assert_eq!(standings[0].score, Decimal::from(20));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn standings_around_player(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "My leaderboard", &pool).await?;
+        let mut players = vec![];
+        for score in [50, 40, 30, 20, 10] {
+            let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+            board.add_member(user.id, &pool).await?;
+            board.submit_score(user.id, score.into(), &pool).await?;
+            players.push(user.id);
+        }
+
+        let query = AroundQuery {
+            count: 1,
+            ..Default::default()
+        };
+        let window = board
+            .around_player(players[1], &query, &TimeWindow::default(), &pool)
+            .await?
+            .unwrap();
+
+        assert_eq!(window.rank, 2);
+        let neighbours: Vec<Uuid> = window.standings.iter().map(|s| s.player).collect();
+        assert_eq!(neighbours, &players[0..3]);
+
+        let window = board
+            .around_player(players[4], &query, &TimeWindow::default(), &pool)
+            .await?
+            .unwrap();
+        assert_eq!(window.rank, 5);
+        assert_eq!(window.standings.len(), 2);
+
+        let missing = board
+            .around_player(Uuid::new_v4(), &query, &TimeWindow::default(), &pool)
+            .await?;
+        assert!(missing.is_none());
+
+        Ok(())
+    }
 }
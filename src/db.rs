@@ -1,12 +1,44 @@
-use redis::{AsyncCommands, aio::MultiplexedConnection};
+use crate::{
+    ClientError, ClientResponse,
+    board::{Aggregation, BoardOptions, SortOrder},
+    hub::BoardEvent,
+    project::DEFAULT_PROJECT,
+    score,
+};
+use redis::{
+    AsyncCommands, Script,
+    aio::{MultiplexedConnection, PubSub},
+};
 use redis_macros::{FromRedisValue, ToRedisArgs};
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
+use std::{fmt::Display, sync::LazyLock};
 use uuid::Uuid;
 
+/// Numbers an event, adds it to the replay buffer and publishes it in one
+/// step, so every node receives the events of a board in the order of their
+/// sequence numbers. The sequence number is spliced into the event's JSON.
+static PUBLISH_EVENT: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r#"
+        local seq = redis.call('INCR', KEYS[1])
+        local event = '{"seq":' .. seq .. ',' .. string.sub(ARGV[1], 2)
+        redis.call('LPUSH', KEYS[2], event)
+        redis.call('LTRIM', KEYS[2], 0, tonumber(ARGV[2]) - 1)
+        redis.call('PUBLISH', KEYS[3], event)
+        return seq
+        "#,
+    )
+});
+
 #[derive(Debug, Serialize, Deserialize, Clone, FromRedisValue, ToRedisArgs)]
 pub struct ScoreBoard {
     id: Uuid,
     users: Vec<User>,
+    #[serde(default)]
+    aggregation: Aggregation,
+    #[serde(default)]
+    sort_order: SortOrder,
 }
 
 impl Default for ScoreBoard {
@@ -17,19 +49,63 @@ impl Default for ScoreBoard {
 
 impl ScoreBoard {
     pub fn new() -> Self {
+        Self::with_options(&BoardOptions::default())
+    }
+
+    /// Create a [`ScoreBoard`] that combines and ranks user scores
+    /// using the [`BoardOptions`]
+    pub fn with_options(options: &BoardOptions) -> Self {
         let id = Uuid::new_v4();
 
-        Self { id, users: vec![] }
+        Self {
+            id,
+            users: vec![],
+            aggregation: options.aggregation,
+            sort_order: options.sort_order,
+        }
     }
 
     pub fn id(&self) -> Uuid {
         self.id
     }
+
+    pub fn aggregation(&self) -> Aggregation {
+        self.aggregation
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    pub fn add_user(&mut self, user: User) {
+        self.users.push(user);
+    }
+
+    /// Get the id and score of every user, ordered by the board's [`SortOrder`].
+    pub fn ranked(&self) -> Result<Vec<(Uuid, Decimal)>, ClientError> {
+        let mut scores = vec![];
+        for user in &self.users {
+            let score = user.score(self.aggregation, self.sort_order)?;
+            scores.push((user.id, score));
+        }
+
+        scores.sort_by(|(_, a), (_, b)| match self.sort_order {
+            SortOrder::Descending => b.cmp(a),
+            SortOrder::Ascending => a.cmp(b),
+        });
+        Ok(scores)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, Copy, FromRedisValue, ToRedisArgs)]
 pub struct Score {
-    value: u64, // TODO maybe make this f64
+    value: Decimal,
+}
+
+impl Score {
+    pub fn value(&self) -> Decimal {
+        self.value
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default, FromRedisValue, ToRedisArgs)]
@@ -51,10 +127,12 @@ impl User {
         self.id
     }
 
-    pub fn add_score(&mut self, score: u64) {
-        let score = Score { value: score };
+    /// Add a score, the score is rounded to [`score::SCALE`] decimal places.
+    pub fn add_score(&mut self, score: Decimal) -> Result<(), ClientError> {
+        let value = score::normalize(score)?;
+        self.scores.push(Score { value });
 
-        self.scores.push(score);
+        Ok(())
     }
 
     pub fn scores(&self) -> Vec<Score> {
@@ -64,25 +142,72 @@ impl User {
     /// Get the user's total score
     ///
     /// ```
-    /// use scoreboard::User;
+    /// use rust_decimal::Decimal;
+    /// use scoreboard::db::User;
     ///
     /// let mut user = User::new();
-    /// user.add_score(20);
-    /// user.add_score(200);
+    /// user.add_score(Decimal::from(20)).unwrap();
+    /// user.add_score(Decimal::new(-25, 1)).unwrap();
     ///
-    /// let total = user.total_score();
+    /// let total = user.total_score().unwrap();
     ///
-    /// assert_eq!(total,220);
+    /// assert_eq!(total, Decimal::new(175, 1));
     /// ```
     ///
-    pub fn total_score(&self) -> u64 {
-        self.scores.iter().fold(0, |acc, score| score.value + acc)
+    pub fn total_score(&self) -> Result<Decimal, ClientError> {
+        self.scores.iter().try_fold(Decimal::ZERO, |acc, score| {
+            score::checked_add(acc, score.value)
+        })
+    }
+
+    /// Get the user's score combined using `aggregation`, the `order` decides
+    /// which score is the best. Users without any scores have a score of `0`.
+    ///
+    /// ```
+    /// use rust_decimal::Decimal;
+    /// use scoreboard::{board::{Aggregation, SortOrder}, db::User};
+    ///
+    /// let mut user = User::new();
+    /// user.add_score(Decimal::from(20)).unwrap();
+    /// user.add_score(Decimal::from(45)).unwrap();
+    ///
+    /// let best = user.score(Aggregation::Best, SortOrder::Ascending).unwrap();
+    /// let average = user.score(Aggregation::Average, SortOrder::Descending).unwrap();
+    ///
+    /// assert_eq!(best, Decimal::from(20));
+    /// assert_eq!(average, Decimal::new(325, 1));
+    /// ```
+    pub fn score(
+        &self,
+        aggregation: Aggregation,
+        order: SortOrder,
+    ) -> Result<Decimal, ClientError> {
+        let mut values = self.scores.iter().map(|score| score.value);
+
+        let score = match aggregation {
+            Aggregation::Sum => self.total_score()?,
+            Aggregation::Best => match order {
+                SortOrder::Descending => values.max().unwrap_or_default(),
+                SortOrder::Ascending => values.min().unwrap_or_default(),
+            },
+            Aggregation::Latest => values.next_back().unwrap_or_default(),
+            Aggregation::Average if self.scores.is_empty() => Decimal::ZERO,
+            Aggregation::Average => {
+                let average = self.total_score()? / Decimal::from(self.scores.len());
+                average.round_dp_with_strategy(score::SCALE, RoundingStrategy::MidpointAwayFromZero)
+            }
+            Aggregation::Count => Decimal::from(self.scores.len()),
+        };
+
+        Ok(score)
     }
 }
 
 #[derive(Clone)]
 pub struct DbClient {
+    client: redis::Client,
     connection: MultiplexedConnection,
+    project: Uuid,
 }
 
 impl DbClient {
@@ -90,14 +215,92 @@ impl DbClient {
         let client = redis::Client::open("redis://[::1]:6379")?;
         let connection = client.get_multiplexed_async_connection().await?;
 
-        Ok(Self { connection })
+        Ok(Self {
+            client,
+            connection,
+            project: DEFAULT_PROJECT,
+        })
+    }
+
+    /// Get a client that reads and writes the keys of a project, the
+    /// connection is shared with this client.
+    pub fn project(&self, project: Uuid) -> Self {
+        Self {
+            client: self.client.clone(),
+            connection: self.connection.clone(),
+            project,
+        }
+    }
+
+    /// Open a new connection for receiving published messages
+    pub async fn pubsub(&self) -> crate::Result<PubSub> {
+        Ok(self.client.get_async_pubsub().await?)
+    }
+
+    /// Get the key of a value, keys in the default project aren't
+    /// namespaced so that values stored before projects can still be read.
+    fn key(&self, kind: &str, id: impl Display) -> String {
+        if self.project == DEFAULT_PROJECT {
+            format!("{kind}:{id}")
+        } else {
+            format!("project:{}:{kind}:{id}", self.project)
+        }
     }
+
     // TODO make generic get and set methods
 
+    /// Get the sequence number of a board's latest event
+    pub async fn event_seq(&mut self, board: i32) -> crate::Result<u64> {
+        let seq: Option<u64> = self.connection.get(self.key("board_seq", board)).await?;
+        Ok(seq.unwrap_or_default())
+    }
+
+    /// Get the channel a board's events are published to, the board's id
+    /// comes last.
+    pub fn event_channel(&self, board: i32) -> String {
+        self.key("board_channel", board)
+    }
+
+    /// Number an event, add it to a board's replay buffer, of which only the
+    /// latest `limit` events are kept, and publish it to the board's channel.
+    pub async fn publish_event(
+        &mut self,
+        board: i32,
+        event: ClientResponse,
+        limit: usize,
+    ) -> crate::Result<BoardEvent> {
+        let seq: u64 = PUBLISH_EVENT
+            .key(self.key("board_seq", board))
+            .key(self.key("board_events", board))
+            .key(self.event_channel(board))
+            .arg(serde_json::to_string(&event)?)
+            .arg(limit)
+            .invoke_async(&mut self.connection)
+            .await?;
+
+        Ok(BoardEvent { seq, event })
+    }
+
+    /// Get the events in a board's replay buffer, oldest first
+    pub async fn get_events(&mut self, board: i32) -> crate::Result<Vec<BoardEvent>> {
+        let events: Vec<String> = self
+            .connection
+            .lrange(self.key("board_events", board), 0, -1)
+            .await?;
+
+        let mut events = events
+            .iter()
+            .map(|event| serde_json::from_str(event))
+            .collect::<Result<Vec<BoardEvent>, _>>()?;
+        events.reverse();
+
+        Ok(events)
+    }
+
     pub async fn set_scoreboard(&mut self, scoreboard: ScoreBoard) -> crate::Result<()> {
         let _: () = self
             .connection
-            .set(format!("scoreboard:{}", scoreboard.id), scoreboard)
+            .set(self.key("scoreboard", scoreboard.id), scoreboard)
             .await?;
 
         Ok(())
@@ -105,7 +308,7 @@ impl DbClient {
 
     pub async fn get_scoreboard(&mut self, id: &Uuid) -> crate::Result<Option<ScoreBoard>> {
         let response: Result<ScoreBoard, redis::RedisError> =
-            self.connection.get(format!("scoreboard:{}", id)).await;
+            self.connection.get(self.key("scoreboard", id)).await;
 
         match response {
             Ok(board) => Ok(Some(board)),
@@ -117,17 +320,14 @@ impl DbClient {
     }
 
     pub async fn set_user(&mut self, user: User) -> crate::Result<()> {
-        let _: () = self
-            .connection
-            .set(format!("user:{}", user.id), user)
-            .await?;
+        let _: () = self.connection.set(self.key("user", user.id), user).await?;
 
         Ok(())
     }
 
     pub async fn get_user(&mut self, id: &Uuid) -> crate::Result<Option<User>> {
         let response: Result<User, redis::RedisError> =
-            self.connection.get(format!("user:{}", id)).await;
+            self.connection.get(self.key("user", id)).await;
 
         match response {
             Ok(user) => Ok(Some(user)),
@@ -176,13 +376,73 @@ mod tests {
         client.set_user(user).await?;
 
         let mut user = client.get_user(&id).await?.unwrap();
-        assert_eq!(user.total_score(), 0);
-        user.add_score(200);
-        user.add_score(2);
+        assert_eq!(user.total_score()?, Decimal::ZERO);
+        user.add_score(Decimal::from(200))?;
+        user.add_score(Decimal::new(25, 1))?;
         client.set_user(user).await?;
 
         let user = client.get_user(&id).await?.unwrap();
-        assert_eq!(user.total_score(), 202);
+        assert_eq!(user.total_score()?, Decimal::new(2025, 1));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scoreboards_are_scoped_to_projects() -> crate::Result<()> {
+        let client = DbClient::new().await?;
+        let mut project = client.project(Uuid::new_v4());
+        let scoreboard = ScoreBoard::new();
+        let id = scoreboard.id;
+        project.set_scoreboard(scoreboard).await?;
+
+        assert!(project.get_scoreboard(&id).await?.is_some());
+        let mut other = client.project(Uuid::new_v4());
+        assert!(other.get_scoreboard(&id).await?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rank_users_by_aggregated_score() -> crate::Result<()> {
+        let options = BoardOptions {
+            aggregation: Aggregation::Best,
+            ..Default::default()
+        };
+        let mut board = ScoreBoard::with_options(&options);
+        let mut user = User::new();
+        user.add_score(Decimal::from(50))?;
+        user.add_score(Decimal::from(5))?;
+        let mut user2 = User::from_id(Uuid::new_v4());
+        user2.add_score(Decimal::from(30))?;
+        user2.add_score(Decimal::from(30))?;
+
+        board.add_user(user.clone());
+        board.add_user(user2.clone());
+
+        let expected = [(user.id, Decimal::from(50)), (user2.id, Decimal::from(30))];
+        assert_eq!(board.ranked()?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rank_ascending_users() -> crate::Result<()> {
+        let options = BoardOptions {
+            aggregation: Aggregation::Best,
+            sort_order: SortOrder::Ascending,
+        };
+        let mut board = ScoreBoard::with_options(&options);
+        let mut user = User::new();
+        user.add_score(Decimal::from(50))?;
+        user.add_score(Decimal::from(5))?;
+        let mut user2 = User::from_id(Uuid::new_v4());
+        user2.add_score(Decimal::from(30))?;
+
+        board.add_user(user.clone());
+        board.add_user(user2.clone());
+
+        let expected = [(user.id, Decimal::from(5)), (user2.id, Decimal::from(30))];
+        assert_eq!(board.ranked()?, expected);
 
         Ok(())
     }
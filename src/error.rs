@@ -18,13 +18,51 @@ pub enum Error {
     #[error(transparent)]
     SerdeJsonError(#[from] serde_json::Error),
     #[error(transparent)]
+    MessagePackEncodeError(#[from] rmp_serde::encode::Error),
+    #[error(transparent)]
+    MessagePackDecodeError(#[from] rmp_serde::decode::Error),
+    #[error(transparent)]
     AxumError(#[from] axum::Error),
     #[error(transparent)]
     AxumHttpError(#[from] axum::http::Error),
     #[error(transparent)]
     SqlxError(#[from] sqlx::Error),
     #[error(transparent)]
-    TungsteniteError(#[from] tokio_tungstenite::tungstenite::Error),
+    TungsteniteError(Box<tokio_tungstenite::tungstenite::Error>),
+    #[error(transparent)]
+    PasswordHashError(#[from] argon2::password_hash::Error),
+    #[error(transparent)]
+    MailError(#[from] lettre::error::Error),
+    #[error(transparent)]
+    MailAddressError(#[from] lettre::address::AddressError),
+    #[error(transparent)]
+    SmtpError(#[from] lettre::transport::smtp::Error),
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JoinError(#[from] tokio::task::JoinError),
+}
+
+impl Error {
+    /// The error reported to clients, errors that weren't caused by the
+    /// client are reported as [`ClientErrorKind::Internal`] without details.
+    pub fn client_error(&self) -> ClientError {
+        match self {
+            Self::ClientError(error) => ClientError::new(&error.message, error.kind),
+            Self::UnsupportedMethod => {
+                ClientError::new(&self.to_string(), ClientErrorKind::UnsupportedMethod)
+            }
+            _ => ClientError::new("An unknown error occured", ClientErrorKind::Internal),
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(error: tokio_tungstenite::tungstenite::Error) -> Self {
+        Self::TungsteniteError(Box::new(error))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,6 +95,30 @@ impl ClientError {
 pub enum ClientErrorKind {
     NotFound,
     UnsupportedMethod,
+    Forbidden,
+    InvalidScore,
+    InvalidInput,
+    InvalidCredentials,
+    Conflict,
+    Unauthorized,
+    Internal,
+}
+
+impl ClientErrorKind {
+    /// The http status code this kind of error is reported with
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UnsupportedMethod => StatusCode::BAD_REQUEST,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::InvalidScore => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidInput => StatusCode::BAD_REQUEST,
+            Self::InvalidCredentials => StatusCode::UNAUTHORIZED,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl std::error::Error for ClientError {}
@@ -69,6 +131,10 @@ impl std::fmt::Display for ClientError {
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
+        if let Self::ClientError(error) = self {
+            return (error.kind.status_code(), Json(error)).into_response();
+        }
+
         let body = Json(json!({"error":"An unknown error occured"}));
 
         (StatusCode::INTERNAL_SERVER_ERROR, body).into_response()
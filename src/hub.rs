@@ -0,0 +1,445 @@
+//! Pushes leaderboard events to the websocket connections subscribed
+//! to a board.
+//!
+//! Every event gets a sequence number that increases per board and the
+//! latest events are kept in Redis, so clients that reconnect to any node
+//! can replay the events they missed. Events are published through Redis,
+//! every node subscribes to the channels of the boards its connections are
+//! subscribed to and sends the events on to them.
+use crate::{
+    ClientResponse,
+    board::{Leaderboard, Point},
+    db::DbClient,
+};
+use futures_util::StreamExt;
+use redis::aio::{PubSubSink, PubSubStream};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    sync::{self, broadcast},
+    task::JoinHandle,
+    time,
+};
+use uuid::Uuid;
+
+/// The number of events a slow subscriber can fall behind by before
+/// it starts missing events.
+pub const CHANNEL_CAPACITY: usize = 64;
+
+/// The number of events kept for each board to replay to clients
+pub const REPLAY_LIMIT: usize = 100;
+
+/// How long to wait before connecting to Redis again after the connection
+/// receiving events was lost
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// An event of a board with its sequence number
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardEvent {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: ClientResponse,
+}
+
+/// The events missed by a client, see [`Hub::replay`]
+#[derive(Debug)]
+pub enum Replay {
+    /// The events after the client's sequence number, oldest first
+    Events(Vec<BoardEvent>),
+    /// Some of the events are no longer kept, the client has to reload
+    /// the board. Contains the board's current sequence number.
+    ResyncRequired(u64),
+}
+
+/// The local subscribers of a board and the Redis channel their events
+/// are received from
+struct Channel {
+    name: String,
+    sender: broadcast::Sender<BoardEvent>,
+}
+
+type Channels = Arc<Mutex<HashMap<i32, Channel>>>;
+
+/// Broadcasts the events of each board to its subscribers
+pub struct Hub {
+    client: DbClient,
+    boards: Channels,
+    /// Changes the node's Redis subscriptions, it's locked while they
+    /// change so that they match the boards.
+    sink: Arc<sync::Mutex<PubSubSink>>,
+    listener: JoinHandle<()>,
+}
+
+impl Hub {
+    /// Create a hub and start receiving the events published to Redis
+    pub async fn new(client: DbClient) -> crate::Result<Self> {
+        let (sink, stream) = client.pubsub().await?.split();
+        let boards = Channels::default();
+        let sink = Arc::new(sync::Mutex::new(sink));
+        let listener = tokio::spawn(listen(client.clone(), stream, boards.clone(), sink.clone()));
+
+        Ok(Self {
+            client,
+            boards,
+            sink,
+            listener,
+        })
+    }
+
+    /// Subscribe to the events of a board, the node subscribes to the
+    /// board's channel when it's the board's first subscriber.
+    pub async fn subscribe(
+        &self,
+        project: Uuid,
+        board: i32,
+    ) -> crate::Result<broadcast::Receiver<BoardEvent>> {
+        let mut sink = self.sink.lock().await;
+        let (events, name) = match self.boards.lock().unwrap().entry(board) {
+            Entry::Occupied(entry) => return Ok(entry.get().sender.subscribe()),
+            Entry::Vacant(entry) => {
+                let name = self.client.project(project).event_channel(board);
+                let (sender, events) = broadcast::channel(CHANNEL_CAPACITY);
+                entry.insert(Channel {
+                    name: name.clone(),
+                    sender,
+                });
+                (events, name)
+            }
+        };
+
+        if let Err(error) = sink.subscribe(&name).await {
+            self.boards.lock().unwrap().remove(&board);
+            return Err(error.into());
+        }
+
+        Ok(events)
+    }
+
+    /// Number an event, keep it for replays and publish it to the
+    /// subscribers of the board on every node. Redis drops events of
+    /// boards without subscribers once they're kept for replays.
+    pub async fn publish(
+        &self,
+        project: Uuid,
+        board: i32,
+        event: ClientResponse,
+    ) -> crate::Result<BoardEvent> {
+        self.client
+            .project(project)
+            .publish_event(board, event, REPLAY_LIMIT)
+            .await
+    }
+
+    /// Get the current sequence number of a board
+    pub async fn seq(&self, project: Uuid, board: i32) -> crate::Result<u64> {
+        self.client.project(project).event_seq(board).await
+    }
+
+    /// Get the events of a board published after `last_seq`
+    pub async fn replay(&self, project: Uuid, board: i32, last_seq: u64) -> crate::Result<Replay> {
+        let mut client = self.client.project(project);
+        let current = client.event_seq(board).await?;
+        if last_seq >= current {
+            // Sequence numbers ahead of the board's were lost by Redis
+            return Ok(match last_seq == current {
+                true => Replay::Events(vec![]),
+                false => Replay::ResyncRequired(current),
+            });
+        }
+
+        let events: Vec<BoardEvent> = client
+            .get_events(board)
+            .await?
+            .into_iter()
+            .filter(|event| event.seq > last_seq)
+            .collect();
+
+        match events.first() {
+            Some(first) if first.seq == last_seq + 1 => Ok(Replay::Events(events)),
+            _ => Ok(Replay::ResyncRequired(current)),
+        }
+    }
+
+    /// Remove the channel of a board if it has no subscribers left and
+    /// unsubscribe the node from it.
+    pub async fn prune(&self, board: i32) {
+        let mut sink = self.sink.lock().await;
+        let removed = {
+            let mut boards = self.boards.lock().unwrap();
+            match boards.get(&board) {
+                Some(channel) if channel.sender.receiver_count() == 0 => boards.remove(&board),
+                _ => None,
+            }
+        };
+
+        if let Some(channel) = removed
+            && let Err(error) = sink.unsubscribe(&channel.name).await
+        {
+            tracing::warn!("Failed to unsubscribe from board {board}: {error}");
+        }
+    }
+
+    /// Get the number of boards with subscribers
+    pub fn board_count(&self) -> usize {
+        self.boards.lock().unwrap().len()
+    }
+
+    /// Get the number of local subscribers of a board
+    pub fn subscriber_count(&self, board: i32) -> usize {
+        let boards = self.boards.lock().unwrap();
+        boards
+            .get(&board)
+            .map(|channel| channel.sender.receiver_count())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Hub {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+/// Send the events received from Redis to the subscribers of their board.
+/// Redis delivers the events of a board in the order of their sequence
+/// numbers. When the connection is lost a new one subscribes to the boards
+/// again, subscribers miss the events published in between and resume from
+/// their last sequence number.
+async fn listen(
+    client: DbClient,
+    mut stream: PubSubStream,
+    boards: Channels,
+    sink: Arc<sync::Mutex<PubSubSink>>,
+) {
+    loop {
+        while let Some(message) = stream.next().await {
+            forward(&message, &boards);
+        }
+
+        tracing::warn!("Lost the connection receiving board events, reconnecting");
+        stream = loop {
+            time::sleep(RECONNECT_DELAY).await;
+            match resubscribe(&client, &boards, &sink).await {
+                Ok(stream) => break stream,
+                Err(error) => tracing::warn!("Failed to receive board events again: {error}"),
+            }
+        };
+    }
+}
+
+/// Open a new connection subscribed to the channels of the boards
+async fn resubscribe(
+    client: &DbClient,
+    boards: &Channels,
+    sink: &sync::Mutex<PubSubSink>,
+) -> crate::Result<PubSubStream> {
+    let mut sink = sink.lock().await;
+    let (mut new_sink, stream) = client.pubsub().await?.split();
+    let names: Vec<String> = {
+        let boards = boards.lock().unwrap();
+        boards
+            .values()
+            .map(|channel| channel.name.clone())
+            .collect()
+    };
+    if !names.is_empty() {
+        new_sink.subscribe(names).await?;
+    }
+    *sink = new_sink;
+
+    Ok(stream)
+}
+
+/// Send an event to the local subscribers of its board, the board's id is
+/// the end of the channel's name.
+fn forward(message: &redis::Msg, boards: &Channels) {
+    let channel = message.get_channel_name();
+    let Some(board) = channel.rsplit(':').next().and_then(|id| id.parse().ok()) else {
+        tracing::warn!("Received an event from an unknown channel {channel}");
+        return;
+    };
+    let event: BoardEvent = match serde_json::from_slice(message.get_payload_bytes()) {
+        Ok(event) => event,
+        Err(error) => {
+            tracing::warn!("Received an invalid event of board {board}: {error}");
+            return;
+        }
+    };
+
+    let boards = boards.lock().unwrap();
+    if let Some(channel) = boards.get(&board) {
+        // Sending only fails when everyone has unsubscribed
+        let _ = channel.sender.send(event);
+    }
+}
+
+/// Submit a score for a player and publish a [`ClientResponse::ScoreUpdated`]
+/// event to the board, followed by [`ClientResponse::RankChanged`] and
+/// [`ClientResponse::StandingsChanged`] events if the player's rank changed.
+/// Events that can't be computed or published are logged since the score
+/// was already submitted.
+pub async fn submit_score(
+    hub: &Hub,
+    board: &Leaderboard,
+    player: Uuid,
+    value: Decimal,
+    pool: &PgPool,
+) -> crate::Result<Point> {
+    let point = board.submit_score(player, value, pool).await?;
+
+    let change = match board.standing_change(&point, pool).await {
+        Ok(Some(change)) => change,
+        Ok(None) => return Ok(point),
+        Err(error) => {
+            tracing::warn!(
+                "Failed to get the standing change on board {}: {error}",
+                board.id
+            );
+            return Ok(point);
+        }
+    };
+    let (after, previous_rank) = (change.standing, change.previous_rank);
+
+    let mut events = vec![ClientResponse::ScoreUpdated {
+        board: board.id,
+        player,
+        value: point.value,
+        score: after.score,
+        rank: after.rank,
+    }];
+
+    if previous_rank != Some(after.rank) {
+        events.push(ClientResponse::RankChanged {
+            board: board.id,
+            player,
+            previous_rank,
+            rank: after.rank,
+        });
+        events.push(ClientResponse::StandingsChanged {
+            board: board.id,
+            from_rank: previous_rank.map_or(after.rank, |rank| rank.min(after.rank)),
+            to_rank: previous_rank.map(|rank| rank.max(after.rank)),
+        });
+    }
+
+    for event in events {
+        if let Err(error) = hub.publish(board.project, board.id, event).await {
+            tracing::warn!("Failed to publish an event of board {}: {error}", board.id);
+        }
+    }
+
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_to_subscribers() -> crate::Result<()> {
+        let hub = Hub::new(DbClient::new().await?).await?;
+        let project = Uuid::new_v4();
+        let mut events = hub.subscribe(project, 1).await?;
+        let mut other = hub.subscribe(project, 2).await?;
+
+        let event = ClientResponse::Unsubscribed { board: 1 };
+        hub.publish(project, 1, event.clone()).await?;
+        let received = events.recv().await.unwrap();
+        assert_eq!(received.seq, 1);
+        assert!(matches!(
+            received.event,
+            ClientResponse::Unsubscribed { board: 1 }
+        ));
+        assert!(other.try_recv().is_err());
+
+        drop(events);
+        let published = hub.publish(project, 1, event).await?;
+        assert_eq!(published.seq, 2);
+        assert_eq!(hub.subscriber_count(1), 0);
+        assert_eq!(hub.subscriber_count(2), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn publish_to_every_node() -> crate::Result<()> {
+        let hub = Hub::new(DbClient::new().await?).await?;
+        let other = Hub::new(DbClient::new().await?).await?;
+        let project = Uuid::new_v4();
+        let mut events = other.subscribe(project, 1).await?;
+
+        let published = futures_util::future::try_join_all((0..10).map(|_| {
+            let event = ClientResponse::Unsubscribed { board: 1 };
+            hub.publish(project, 1, event)
+        }))
+        .await?;
+        assert_eq!(published.len(), 10);
+        for seq in 1..=10 {
+            assert_eq!(events.recv().await.unwrap().seq, seq);
+        }
+
+        drop(events);
+        other.prune(1).await;
+        assert_eq!(other.board_count(), 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_missed_events() -> crate::Result<()> {
+        let hub = Hub::new(DbClient::new().await?).await?;
+        let project = Uuid::new_v4();
+        for _ in 0..REPLAY_LIMIT + 5 {
+            let event = ClientResponse::Unsubscribed { board: 1 };
+            hub.publish(project, 1, event).await?;
+        }
+
+        let current = hub.seq(project, 1).await?;
+        assert_eq!(current, REPLAY_LIMIT as u64 + 5);
+
+        let Replay::Events(events) = hub.replay(project, 1, current - 3).await? else {
+            panic!("Expected the missed events");
+        };
+        let seqs: Vec<u64> = events.iter().map(|event| event.seq).collect();
+        assert_eq!(seqs, vec![current - 2, current - 1, current]);
+
+        let Replay::Events(events) = hub.replay(project, 1, current).await? else {
+            panic!("Expected no events");
+        };
+        assert!(events.is_empty());
+
+        // The oldest events are no longer kept
+        assert!(matches!(
+            hub.replay(project, 1, 2).await?,
+            Replay::ResyncRequired(seq) if seq == current
+        ));
+        assert!(matches!(
+            hub.replay(project, 1, current + 1).await?,
+            Replay::ResyncRequired(_)
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_events_without_subscribers() -> crate::Result<()> {
+        let hub = Hub::new(DbClient::new().await?).await?;
+        let project = Uuid::new_v4();
+        let event = ClientResponse::Unsubscribed { board: 1 };
+        hub.publish(project, 1, event).await?;
+        assert_eq!(hub.board_count(), 0);
+
+        let Replay::Events(events) = hub.replay(project, 1, 0).await? else {
+            panic!("Expected the missed events");
+        };
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+}
@@ -1,24 +1,60 @@
 pub mod api;
+pub mod api_key;
 pub mod auth;
 pub mod board;
 pub mod db;
 mod error;
+pub mod hub;
+pub mod mail;
+pub mod project;
+pub mod role;
+pub mod score;
+pub mod season;
+pub mod token;
+pub mod window;
 pub mod ws;
 use axum::{
     Router,
-    extract::{
-        State, WebSocketUpgrade,
-        ws::{Message, WebSocket},
-    },
-    response::Response,
-    routing::{any, get, post},
+    middleware::from_fn_with_state,
+    routing::{any, delete, get, patch, post, put},
 };
+use board::Point;
 use db::{DbClient, ScoreBoard};
 pub use error::{ClientError, ClientErrorKind, Error, Result};
+use hub::{BoardEvent, Hub};
+use mail::{Mailer, MemoryMailer};
+use role::Permission;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
-use std::env;
+use std::{env, sync::Arc, time::Duration};
 use uuid::Uuid;
+use ws::SocketConfig;
+pub use ws::handle_message;
+
+/// Wraps a message or response with the id the client chose for the
+/// request, responses echo the id of the message they answer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Envelope<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<MessageId>,
+    #[serde(flatten)]
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(id: Option<MessageId>, message: T) -> Self {
+        Self { id, message }
+    }
+}
+
+/// The id of a request, clients can use numbers or strings
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum MessageId {
+    Number(i64),
+    Text(String),
+}
 
 /// All the message types that can be sent over the web socket
 /// connection
@@ -26,85 +62,133 @@ use uuid::Uuid;
 #[serde(tag = "method", content = "body")]
 #[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
 pub enum ClientMessage {
-    AddMember { name: String },
-    DeleteMember { name: String },
-    UpdateScore { name: String, score: u64 },
+    AddMember {
+        board: i32,
+        player: Uuid,
+    },
+    DeleteMember {
+        board: i32,
+        player: Uuid,
+    },
+    /// Submit a score, the player defaults to the signed in user
+    UpdateScore {
+        board: i32,
+        player: Option<Uuid>,
+        score: Decimal,
+    },
     CreateScoreBoard,
-    GetScoreBoard { id: Uuid },
+    GetScoreBoard {
+        id: Uuid,
+    },
+    /// Authenticate a connection made without credentials with a session
+    /// token or API key, only accepted as the first message.
+    Authenticate {
+        token: String,
+    },
+    /// Receive the events of a leaderboard
+    Subscribe {
+        board: i32,
+    },
+    /// Subscribe to a board again after reconnecting and receive the
+    /// events published after `last_seq`
+    Resume {
+        board: i32,
+        last_seq: u64,
+    },
+    Unsubscribe {
+        board: i32,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "method", content = "body")]
 #[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
 pub enum ClientResponse {
-    CreateScoreBoard { id: Uuid },
-    GetScoreBoard { scoreboard: ScoreBoard },
-}
-
-async fn _handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    ws.on_upgrade(async |socket| {
-        match _handle_socket(socket, state).await {
-            Ok(_) => {}
-            Err(_) => {} // FIXME
-        };
-    })
-}
-
-async fn _handle_socket(mut socket: WebSocket, mut state: AppState) -> crate::Result<()> {
-    // let (sender,receiver) =  socket.split();
-    while let Some(msg) = socket.recv().await {
-        if let Message::Text(text) = msg? {
-            match serde_json::from_str::<ClientMessage>(&text) {
-                Ok(message) => {
-                    let response = handle_message(message, &mut state).await?;
-                    let message = serde_json::to_string(&response)?;
-
-                    socket.send(Message::Text(message.into())).await?;
-                }
-                Err(err) => {
-                    dbg!(err);
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
-pub async fn handle_message(
-    message: ClientMessage,
-    state: &mut AppState,
-) -> Result<ClientResponse> {
-    let redis = state.client();
-
-    match message {
-        ClientMessage::CreateScoreBoard => {
-            let board = ScoreBoard::new();
-            let id = board.id();
-
-            redis.set_scoreboard(board).await?;
-            let response = ClientResponse::CreateScoreBoard { id };
-
-            Ok(response)
-        }
-        ClientMessage::GetScoreBoard { id } => match redis.get_scoreboard(&id).await? {
-            Some(scoreboard) => {
-                let response = ClientResponse::GetScoreBoard { scoreboard };
-                Ok(response)
-            }
-            None => {
-                let error = ClientError::not_found("Scoreboard not found");
-                Err(error.into())
-            }
-        },
-        _ => Err(Error::UnsupportedMethod),
-    }
+    AddMember {
+        board: i32,
+        player: Uuid,
+    },
+    DeleteMember {
+        board: i32,
+        player: Uuid,
+    },
+    UpdateScore {
+        point: Point,
+    },
+    CreateScoreBoard {
+        id: Uuid,
+    },
+    GetScoreBoard {
+        scoreboard: ScoreBoard,
+    },
+    /// The connection is authenticated, `user` is `None` for API keys
+    Authenticated {
+        user: Option<Uuid>,
+        project: Uuid,
+    },
+    /// `seq` is the sequence number of the board's latest event
+    Subscribed {
+        board: i32,
+        seq: u64,
+    },
+    /// The events missed since the sequence number sent with
+    /// [`ClientMessage::Resume`], oldest first
+    Resumed {
+        board: i32,
+        events: Vec<BoardEvent>,
+    },
+    /// Some of the missed events are no longer kept, the client has to
+    /// reload the board. `seq` is the sequence number of the board's
+    /// latest event. Also pushed to subscribers that fell too far behind.
+    ResyncRequired {
+        board: i32,
+        seq: u64,
+    },
+    Unsubscribed {
+        board: i32,
+    },
+    /// Pushed to subscribers when a player submits a score, `score`
+    /// is the player's combined score after the submission.
+    ScoreUpdated {
+        board: i32,
+        player: Uuid,
+        value: Decimal,
+        score: Decimal,
+        rank: i64,
+    },
+    /// Sent when a message couldn't be handled, the trace id is logged
+    /// with the error. It's unrelated to the id of the message, which is
+    /// echoed in the envelope.
+    Error {
+        kind: ClientErrorKind,
+        message: String,
+        trace_id: Uuid,
+    },
+    /// Pushed to subscribers when a submission changes the player's rank
+    RankChanged {
+        board: i32,
+        player: Uuid,
+        previous_rank: Option<i64>,
+        rank: i64,
+    },
+    /// Pushed with [`ClientResponse::RankChanged`] since the players the
+    /// player moved past changed rank too. The ranks from `from_rank` to
+    /// `to_rank` may have changed, through the end of the board if
+    /// `to_rank` is `None`, so clients reload that part of the standings.
+    StandingsChanged {
+        board: i32,
+        from_rank: i64,
+        to_rank: Option<i64>,
+    },
 }
 
 #[derive(Clone)]
 pub struct AppState {
     client: DbClient,
     pool: PgPool,
+    mailer: Arc<dyn Mailer>,
+    hub: Arc<Hub>,
+    socket_config: SocketConfig,
 }
 
 impl AppState {
@@ -115,22 +199,46 @@ impl AppState {
             .max_connections(15)
             .connect(&database_url)
             .await?;
+        let mailer = mail::from_env()?;
+        let hub = Arc::new(Hub::new(client.clone()).await?);
 
         Ok(Self {
             client,
             pool,
+            mailer,
+            hub,
+            socket_config: SocketConfig::from_env(),
         })
     }
 
+    /// Create the state with an existing pool, emails are kept in memory.
     pub async fn with_pool(pool: PgPool) -> crate::Result<Self> {
         let client = DbClient::new().await?;
+        let mailer = Arc::new(MemoryMailer::default());
+
+        let hub = Arc::new(Hub::new(client.clone()).await?);
 
         Ok(Self {
             client,
             pool,
+            mailer,
+            hub,
+            socket_config: SocketConfig::default(),
         })
     }
 
+    /// Replace the mailer
+    pub fn with_mailer(mut self, mailer: impl Mailer + 'static) -> Self {
+        self.mailer = Arc::new(mailer);
+        self
+    }
+
+    /// Replace the websocket config
+    pub fn with_socket_config(mut self, config: SocketConfig) -> Self {
+        self.socket_config = config;
+        self
+    }
+
     /// Get a reference to the client
     pub fn client(&mut self) -> &mut DbClient {
         &mut self.client
@@ -140,12 +248,85 @@ impl AppState {
     pub fn pool(&self) -> &PgPool {
         &self.pool
     }
+
+    /// Get a reference to the hub that pushes board events
+    pub fn hub(&self) -> &Hub {
+        &self.hub
+    }
+
+    /// Get a reference to the websocket config
+    pub fn socket_config(&self) -> &SocketConfig {
+        &self.socket_config
+    }
+
+    /// Get a reference to the mailer
+    pub fn mailer(&self) -> &dyn Mailer {
+        self.mailer.as_ref()
+    }
 }
 
 pub fn router(state: AppState) -> Router {
+    let guard =
+        |permission: Permission| from_fn_with_state((state.clone(), permission), role::guard);
+
     let api = Router::new()
+        .route("/auth/sign-up", post(api::sign_up))
         .route("/auth/sign-up/anonymous", post(api::anon_sign_up))
-        .route("/leaderboard", post(api::create_board))
+        .route("/auth/upgrade", post(api::upgrade))
+        .route("/auth/upgrade/merge", post(api::merge))
+        .route("/auth/login", post(api::login))
+        .route("/auth/logout", post(api::logout))
+        .route("/auth/user", get(api::get_current_user))
+        .route("/auth/password-reset", post(api::request_password_reset))
+        .route("/auth/password-reset/confirm", post(api::reset_password))
+        .route("/auth/verify-email", post(api::request_email_verification))
+        .route("/auth/verify-email/confirm", post(api::verify_email))
+        .route(
+            "/api-keys",
+            post(api::create_api_key).route_layer(guard(Permission::ManageApiKeys)),
+        )
+        .route("/api-keys", get(api::get_api_keys))
+        .route("/api-keys/{id}", delete(api::revoke_api_key))
+        .route(
+            "/leaderboard",
+            post(api::create_board).route_layer(guard(Permission::CreateBoard)),
+        )
+        .route(
+            "/leaderboard/{id}",
+            patch(api::rename_board).route_layer(guard(Permission::UpdateBoard)),
+        )
+        .route(
+            "/leaderboard/{id}",
+            delete(api::delete_board).route_layer(guard(Permission::DeleteBoard)),
+        )
+        .route(
+            "/leaderboard/{id}/members",
+            post(api::add_board_member).route_layer(guard(Permission::ManageMembers)),
+        )
+        .route(
+            "/leaderboard/{id}/members/{player}",
+            delete(api::remove_board_member).route_layer(guard(Permission::ManageMembers)),
+        )
+        .route("/leaderboard/{id}/scores", post(api::submit_score))
+        .route("/leaderboard/{id}/standings", get(api::get_standings))
+        .route(
+            "/leaderboard/{id}/players/{player}/around",
+            get(api::get_standings_around),
+        )
+        .route(
+            "/leaderboard/{id}/season-schedule",
+            put(api::set_season_schedule).route_layer(guard(Permission::UpdateBoard)),
+        )
+        .route("/leaderboard/{id}/seasons", get(api::get_seasons))
+        .route("/seasons/{id}/standings", get(api::get_season_standings))
+        .route(
+            "/users/{id}/role",
+            put(api::set_user_role).route_layer(guard(Permission::ManageRoles)),
+        )
+        .route(
+            "/projects",
+            post(api::create_project).route_layer(guard(Permission::CreateProject)),
+        )
         .route("/leaderboards", get(api::get_leaderboards));
 
     Router::new()
@@ -154,9 +335,29 @@ pub fn router(state: AppState) -> Router {
         .with_state(state)
 }
 
+/// Make the registered user with the `OWNER_EMAIL` the owner of the default
+/// project. Anyone can sign up to the default project, so its owner is set
+/// up by whoever runs the server.
+async fn promote_owner_from_env(pool: &PgPool) -> crate::Result<()> {
+    let Ok(email) = env::var("OWNER_EMAIL") else {
+        return Ok(());
+    };
+
+    if auth::promote_owner(project::DEFAULT_PROJECT, &email, pool)
+        .await?
+        .is_none()
+    {
+        tracing::warn!("No registered user has the OWNER_EMAIL {email}");
+    }
+
+    Ok(())
+}
+
 pub async fn main() -> crate::Result<()> {
     let _ = dotenv::dotenv();
     let state = AppState::new().await?;
+    promote_owner_from_env(state.pool()).await?;
+    season::spawn_archiver(state.pool().clone(), Duration::from_secs(60));
     let app = router(state);
 
     let listener = tokio::net::TcpListener::bind("[::1]:5000").await.unwrap();
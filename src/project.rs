@@ -0,0 +1,142 @@
+//! Projects keep the leaderboards, users and API keys of each game apart.
+//!
+//! Every request is made in a project, callers with a session or API key
+//! use the project they belong to while unauthenticated requests can pick
+//! a project with the `x-project-id` header.
+use crate::{
+    AppState, ClientError, ClientErrorKind,
+    auth::{self, Caller, User},
+    role::Role,
+};
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{HeaderMap, request::Parts},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, prelude::FromRow};
+use uuid::Uuid;
+
+/// The project used when a request doesn't name one
+pub const DEFAULT_PROJECT: Uuid = Uuid::nil();
+
+/// The header unauthenticated requests pick a project with
+pub const PROJECT_HEADER: &str = "x-project-id";
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Project {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Project {
+    /// Create a new project
+    pub async fn create(name: &str, executor: impl PgExecutor<'_>) -> crate::Result<Self> {
+        let name = name.trim();
+        if name.is_empty() {
+            let error =
+                ClientError::new("A project name is required", ClientErrorKind::InvalidInput);
+            return Err(error.into());
+        }
+
+        let project: Project = sqlx::query_as("INSERT INTO projects(name) VALUES($1) RETURNING *")
+            .bind(name)
+            .fetch_one(executor)
+            .await?;
+
+        Ok(project)
+    }
+
+    /// Create a new project along with its owner, who logs in to the
+    /// project with the email and password.
+    pub async fn create_with_owner(
+        name: &str,
+        email: &str,
+        password: &str,
+        pool: &PgPool,
+    ) -> crate::Result<(Self, User)> {
+        let email = auth::validate_email(email)?;
+        let hash = auth::hash_password(password).await?;
+
+        let mut transaction = pool.begin().await?;
+        let project = Self::create(name, &mut *transaction).await?;
+        let owner =
+            auth::insert_user(project.id, &email, &hash, Role::Owner, &mut *transaction).await?;
+        transaction.commit().await?;
+
+        Ok((project, owner))
+    }
+
+    /// Get a project by it's id
+    pub async fn get(id: Uuid, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let project: Option<Project> = sqlx::query_as("SELECT * FROM projects WHERE id = $1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(project)
+    }
+}
+
+/// Extracts the id of the project a request is made in.
+///
+/// Callers with a session or API key are always in their own project,
+/// naming a different project in the `x-project-id` header is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProjectId(pub Uuid);
+
+impl ProjectId {
+    /// Resolve the project of a request from its caller and the project
+    /// it names in the [`PROJECT_HEADER`].
+    pub async fn resolve(
+        caller: Option<&Caller>,
+        header: Option<Uuid>,
+        pool: &PgPool,
+    ) -> crate::Result<Self> {
+        match (caller, header) {
+            (Some(caller), Some(id)) if caller.project() != id => {
+                let error =
+                    ClientError::new("Cannot access another project", ClientErrorKind::Forbidden);
+                Err(error.into())
+            }
+            (Some(caller), _) => Ok(Self(caller.project())),
+            (None, Some(id)) => match Project::get(id, pool).await? {
+                Some(project) => Ok(Self(project.id)),
+                None => Err(ClientError::not_found("Project not found").into()),
+            },
+            (None, None) => Ok(Self(DEFAULT_PROJECT)),
+        }
+    }
+}
+
+/// Get the project named in the [`PROJECT_HEADER`]
+pub fn project_header(headers: &HeaderMap) -> Result<Option<Uuid>, ClientError> {
+    let Some(header) = headers.get(PROJECT_HEADER) else {
+        return Ok(None);
+    };
+
+    header
+        .to_str()
+        .ok()
+        .and_then(|header| header.trim().parse::<Uuid>().ok())
+        .map(Some)
+        .ok_or(ClientError::new(
+            "Malformed project header",
+            ClientErrorKind::InvalidInput,
+        ))
+}
+
+impl FromRequestParts<AppState> for ProjectId {
+    type Rejection = crate::Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> crate::Result<Self> {
+        let header = project_header(&parts.headers)?;
+        let caller =
+            <Caller as OptionalFromRequestParts<AppState>>::from_request_parts(parts, state)
+                .await?;
+
+        Self::resolve(caller.as_ref(), header, state.pool()).await
+    }
+}
@@ -0,0 +1,84 @@
+//! Roles decide what users can do in their project.
+//!
+//! Routes are protected by layering [`guard`] on them with the
+//! [`Permission`] they need, API keys have the permissions of their
+//! scopes that the user who created them still has.
+use crate::{AppState, auth::Caller};
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+
+/// The role of a user in their project
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, sqlx::Type,
+)]
+#[sqlx(type_name = "user_role", rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    Owner,
+    Admin,
+    Moderator,
+    #[default]
+    Player,
+}
+
+/// An action that needs a role
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    CreateBoard,
+    /// Rename a board or change its season schedule
+    UpdateBoard,
+    DeleteBoard,
+    /// Add and remove the players of a board
+    ManageMembers,
+    /// Change the roles of users
+    ManageRoles,
+    /// Create API keys, e.g. for game servers submitting scores
+    ManageApiKeys,
+    /// Create other projects
+    CreateProject,
+}
+
+impl Role {
+    /// Check if the role has a permission
+    ///
+    /// ```
+    /// use scoreboard::role::{Permission, Role};
+    ///
+    /// assert!(Role::Admin.can(Permission::DeleteBoard));
+    /// assert!(!Role::Moderator.can(Permission::DeleteBoard));
+    /// assert!(!Role::Player.can(Permission::ManageApiKeys));
+    /// ```
+    pub fn can(&self, permission: Permission) -> bool {
+        match self {
+            Self::Owner => true,
+            Self::Admin => !matches!(
+                permission,
+                Permission::ManageRoles | Permission::CreateProject
+            ),
+            Self::Moderator => matches!(
+                permission,
+                Permission::UpdateBoard | Permission::ManageMembers
+            ),
+            Self::Player => false,
+        }
+    }
+}
+
+/// Middleware that rejects requests from callers without the permission,
+/// layer it on a route with
+/// `middleware::from_fn_with_state((state, permission), role::guard)`.
+pub async fn guard(
+    State((state, permission)): State<(AppState, Permission)>,
+    request: Request,
+    next: Next,
+) -> crate::Result<Response> {
+    let (mut parts, body) = request.into_parts();
+    let caller = Caller::from_request_parts(&mut parts, &state).await?;
+    caller.require(permission)?;
+
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
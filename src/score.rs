@@ -0,0 +1,82 @@
+use crate::{ClientError, ClientErrorKind};
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// The number of decimal places scores are stored with
+pub const SCALE: u32 = 4;
+
+/// The largest score that can be stored, the `NUMERIC(10, 4)` columns
+/// hold up to 999999.9999
+pub const MAX: Decimal = Decimal::from_parts(1_410_065_407, 2, 0, false, SCALE);
+
+/// The smallest score that can be stored
+pub const MIN: Decimal = Decimal::from_parts(1_410_065_407, 2, 0, true, SCALE);
+
+/// Round a score to [`SCALE`] decimal places, halfway values are rounded
+/// away from zero like postgres does. Returns an error if the rounded
+/// score is outside [`MIN`] and [`MAX`].
+///
+/// ```
+/// use rust_decimal::Decimal;
+/// use scoreboard::score;
+///
+/// let value = score::normalize("12.34565".parse().unwrap()).unwrap();
+/// assert_eq!(value, "12.3457".parse::<Decimal>().unwrap());
+///
+/// assert!(score::normalize(Decimal::from(1_000_000)).is_err());
+/// ```
+pub fn normalize(value: Decimal) -> Result<Decimal, ClientError> {
+    let value = value.round_dp_with_strategy(SCALE, RoundingStrategy::MidpointAwayFromZero);
+
+    if value > MAX || value < MIN {
+        let message = format!("Scores must be between {MIN} and {MAX}");
+        return Err(ClientError::new(&message, ClientErrorKind::InvalidScore));
+    }
+
+    Ok(value)
+}
+
+/// Add two scores, returns an error if the sum overflows.
+pub fn checked_add(a: Decimal, b: Decimal) -> Result<Decimal, ClientError> {
+    a.checked_add(b).ok_or(ClientError::new(
+        "Score overflowed",
+        ClientErrorKind::InvalidScore,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_half_away_from_zero() {
+        let value = normalize("-0.00005".parse().unwrap()).unwrap();
+        assert_eq!(value, "-0.0001".parse::<Decimal>().unwrap());
+    }
+
+    #[test]
+    fn negative_scores_in_range() {
+        assert_eq!(normalize(MIN).unwrap(), MIN);
+        assert_eq!(normalize(MAX).unwrap(), MAX);
+        assert!(normalize(MIN - Decimal::ONE).is_err());
+    }
+
+    #[test]
+    fn scores_up_to_the_column_limits() {
+        let max: Decimal = "999999.9999".parse().unwrap();
+        assert_eq!(MAX, max);
+        assert_eq!(MIN, -max);
+        assert!(normalize(Decimal::from(150_000)).is_ok());
+
+        let step: Decimal = "0.0001".parse().unwrap();
+        assert!(normalize(MAX + step).is_err());
+        assert!(normalize(MIN - step).is_err());
+        // Rounds up past the limit
+        assert!(normalize("999999.99995".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn overflowing_sum() {
+        let error = checked_add(Decimal::MAX, Decimal::ONE).unwrap_err();
+        assert_eq!(error.kind(), ClientErrorKind::InvalidScore);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::board::{Leaderboard, RankMode, Standing, StandingsQuery};
+use chrono::{DateTime, Days, Months, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, prelude::FromRow};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long each season of a leaderboard lasts
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "season_length", rename_all = "lowercase")]
+#[serde(rename_all = "camelCase")]
+pub enum SeasonLength {
+    Day,
+    Week,
+    Month,
+}
+
+impl SeasonLength {
+    /// Get the time `count` seasons after `start`
+    fn advance(&self, start: DateTime<Utc>, count: u32) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Day => start.checked_add_days(Days::new(count.into())),
+            Self::Week => start.checked_add_days(Days::new(u64::from(count) * 7)),
+            Self::Month => start.checked_add_months(Months::new(count)),
+        }
+    }
+}
+
+/// Seasons of a leaderboard start at `starts_at` and follow each
+/// other back to back.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SeasonSchedule {
+    pub leaderboard: i32,
+    pub starts_at: DateTime<Utc>,
+    pub length: SeasonLength,
+}
+
+impl SeasonSchedule {
+    /// Set the season schedule of a leaderboard, replacing the
+    /// previous schedule.
+    pub async fn set(
+        leaderboard: &Leaderboard,
+        starts_at: DateTime<Utc>,
+        length: SeasonLength,
+        pool: &PgPool,
+    ) -> crate::Result<Self> {
+        let mut transaction = pool.begin().await?;
+        let schedule: SeasonSchedule = sqlx::query_as(
+            "INSERT INTO season_schedules(leaderboard,starts_at,length)
+            VALUES($1,$2,$3)
+            ON CONFLICT(leaderboard) DO UPDATE
+            SET starts_at = EXCLUDED.starts_at, length = EXCLUDED.length
+            RETURNING *",
+        )
+        .bind(leaderboard.id)
+        .bind(starts_at)
+        .bind(length)
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        // Upcoming seasons of the previous schedule never start
+        sqlx::query("DELETE FROM seasons WHERE leaderboard = $1 AND starts_at > now()")
+            .bind(leaderboard.id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        schedule.create_seasons(pool).await?;
+        Ok(schedule)
+    }
+
+    /// Get the season schedule of a leaderboard
+    pub async fn get(leaderboard: &Leaderboard, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let schedule: Option<SeasonSchedule> =
+            sqlx::query_as("SELECT * FROM season_schedules WHERE leaderboard = $1")
+                .bind(leaderboard.id)
+                .fetch_optional(pool)
+                .await?;
+
+        Ok(schedule)
+    }
+
+    /// Create the season running now and the season after it, so
+    /// that scores are attributed to a season as soon as it starts.
+    pub async fn create_seasons(&self, pool: &PgPool) -> crate::Result<()> {
+        let current = self.bounds_at(Utc::now());
+        let next = match current {
+            Some((_, ends_at)) => self.bounds_at(ends_at),
+            None => self.bounds_at(self.starts_at),
+        };
+
+        for (starts_at, ends_at) in current.into_iter().chain(next) {
+            sqlx::query(
+                "INSERT INTO seasons(leaderboard,starts_at,ends_at)
+                VALUES($1,$2,$3)
+                ON CONFLICT(leaderboard,starts_at) DO NOTHING",
+            )
+            .bind(self.leaderboard)
+            .bind(starts_at)
+            .bind(ends_at)
+            .execute(pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the start and end of the season running at `time`, returns
+    /// `None` if the first season hasn't started yet.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use scoreboard::season::{SeasonLength, SeasonSchedule};
+    ///
+    /// let schedule = SeasonSchedule {
+    ///     leaderboard: 1,
+    ///     starts_at: Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap(),
+    ///     length: SeasonLength::Month,
+    /// };
+    ///
+    /// let time = Utc.with_ymd_and_hms(2025, 3, 15, 0, 0, 0).unwrap();
+    /// let (start, end) = schedule.bounds_at(time).unwrap();
+    ///
+    /// assert_eq!(start, Utc.with_ymd_and_hms(2025, 2, 28, 12, 0, 0).unwrap());
+    /// assert_eq!(end, Utc.with_ymd_and_hms(2025, 3, 31, 12, 0, 0).unwrap());
+    /// ```
+    pub fn bounds_at(&self, time: DateTime<Utc>) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        if time < self.starts_at {
+            return None;
+        }
+
+        // Skip ahead for day and week lengths instead of stepping
+        // through every season.
+        let mut count = match self.length {
+            SeasonLength::Day => (time - self.starts_at).num_days(),
+            SeasonLength::Week => (time - self.starts_at).num_weeks(),
+            SeasonLength::Month => 0,
+        } as u32;
+
+        loop {
+            // Always advance from the first season so months don't
+            // drift after a short month.
+            let start = self.length.advance(self.starts_at, count)?;
+            let end = self.length.advance(self.starts_at, count + 1)?;
+            if time < end {
+                return Some((start, end));
+            }
+            count += 1;
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Season {
+    pub id: i32,
+    pub leaderboard: i32,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+impl Season {
+    /// Get the season that is currently running on the leaderboard, returns
+    /// `None` if the board has no season schedule or the season hasn't
+    /// been created yet.
+    pub async fn current(leaderboard: &Leaderboard, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let Some(schedule) = SeasonSchedule::get(leaderboard, pool).await? else {
+            return Ok(None);
+        };
+        let Some((starts_at, ends_at)) = schedule.bounds_at(Utc::now()) else {
+            return Ok(None);
+        };
+
+        // Seasons are created ahead of time by the archiver
+        let season: Option<Season> = sqlx::query_as(
+            "SELECT * FROM seasons WHERE leaderboard = $1 AND starts_at = $2 AND ends_at = $3",
+        )
+        .bind(leaderboard.id)
+        .bind(starts_at)
+        .bind(ends_at)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(season)
+    }
+
+    /// Get a season of a leaderboard in the project by it's id
+    pub async fn get(project: Uuid, id: i32, pool: &PgPool) -> crate::Result<Option<Self>> {
+        let season: Option<Season> = sqlx::query_as(
+            "SELECT s.* FROM seasons s 
+            JOIN leaderboards l ON l.id = s.leaderboard 
+            WHERE s.id = $1 AND l.project = $2",
+        )
+        .bind(id)
+        .bind(project)
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(season)
+    }
+
+    /// Get all the seasons of a leaderboard that have started, the
+    /// latest season first
+    pub async fn all(leaderboard: &Leaderboard, pool: &PgPool) -> crate::Result<Vec<Self>> {
+        let seasons: Vec<Season> = sqlx::query_as(
+            "SELECT * FROM seasons
+            WHERE leaderboard = $1 AND starts_at <= now()
+            ORDER BY starts_at DESC",
+        )
+        .bind(leaderboard.id)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(seasons)
+    }
+
+    /// Freeze the final standings of the season into the
+    /// `season_standings` table, seasons are only archived once. Returns
+    /// `false` if the season was already archived.
+    pub async fn archive(
+        &mut self,
+        leaderboard: &Leaderboard,
+        pool: &PgPool,
+    ) -> crate::Result<bool> {
+        let mut transaction = pool.begin().await?;
+
+        // Locks the season so it can't be archived twice at the same time
+        let archived_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+            "UPDATE seasons SET archived_at = now() 
+            WHERE id = $1 AND archived_at IS NULL 
+            RETURNING archived_at",
+        )
+        .bind(self.id)
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+        let Some(archived_at) = archived_at else {
+            return Ok(false);
+        };
+
+        let sql = format!(
+            "{} INSERT INTO season_standings(season,rank,player,player_alias,score)
+            SELECT $3,rank,player,player_alias,score FROM ranked",
+            leaderboard.ranked_sql(RankMode::Competition)
+        );
+        sqlx::query(&sql)
+            .bind(leaderboard.id)
+            .bind(None::<DateTime<Utc>>)
+            .bind(self.id)
+            .execute(&mut *transaction)
+            .await?;
+
+        transaction.commit().await?;
+        self.archived_at = Some(archived_at);
+
+        Ok(true)
+    }
+
+    /// Get the archived standings of the season, returns `None` if the
+    /// season hasn't been archived yet.
+    pub async fn standings(
+        &self,
+        query: &StandingsQuery,
+        pool: &PgPool,
+    ) -> crate::Result<Option<Vec<Standing>>> {
+        if self.archived_at.is_none() {
+            return Ok(None);
+        }
+
+        let standings: Vec<Standing> = sqlx::query_as(
+            "SELECT rank,player,player_alias,score FROM season_standings
+            WHERE season = $1
+            ORDER BY rank, player
+            LIMIT $2 OFFSET $3",
+        )
+        .bind(self.id)
+        .bind(query.limit.clamp(0, StandingsQuery::MAX_LIMIT))
+        .bind(query.offset.max(0))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Some(standings))
+    }
+}
+
+/// Archive every season that has ended and create the upcoming seasons
+/// of every schedule, returns the number of seasons archived. Failing
+/// seasons are logged and skipped.
+pub async fn archive_ended_seasons(pool: &PgPool) -> crate::Result<usize> {
+    let schedules: Vec<SeasonSchedule> = sqlx::query_as("SELECT * FROM season_schedules")
+        .fetch_all(pool)
+        .await?;
+    for schedule in schedules {
+        if let Err(error) = schedule.create_seasons(pool).await {
+            let leaderboard = schedule.leaderboard;
+            tracing::error!("Failed to create seasons of leaderboard {leaderboard}: {error}");
+        }
+    }
+
+    let seasons: Vec<Season> = sqlx::query_as(
+        "SELECT * FROM seasons WHERE archived_at IS NULL AND ends_at <= now() ORDER BY ends_at",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut count = 0;
+    for mut season in seasons {
+        match archive_season(&mut season, pool).await {
+            Ok(true) => count += 1,
+            Ok(false) => {}
+            Err(error) => tracing::error!("Failed to archive season {}: {error}", season.id),
+        }
+    }
+
+    Ok(count)
+}
+
+async fn archive_season(season: &mut Season, pool: &PgPool) -> crate::Result<bool> {
+    // Seasons of every project are archived
+    let leaderboard: Leaderboard = sqlx::query_as("SELECT * FROM leaderboards WHERE id = $1")
+        .bind(season.leaderboard)
+        .fetch_one(pool)
+        .await?;
+    season.archive(&leaderboard, pool).await
+}
+
+/// Periodically archive ended seasons in the background
+pub fn spawn_archiver(pool: PgPool, period: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match archive_ended_seasons(&pool).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Archived {count} seasons"),
+                Err(error) => tracing::error!("Failed to archive seasons: {error}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{auth::create_anon_user, project::DEFAULT_PROJECT, window::TimeWindow};
+    use chrono::TimeDelta;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn weekly_bounds() {
+        let starts_at = Utc::now() - TimeDelta::days(20);
+        let schedule = SeasonSchedule {
+            leaderboard: 1,
+            starts_at,
+            length: SeasonLength::Week,
+        };
+
+        let (start, end) = schedule.bounds_at(Utc::now()).unwrap();
+        assert_eq!(start, starts_at + TimeDelta::weeks(2));
+        assert_eq!(end, starts_at + TimeDelta::weeks(3));
+        assert!(
+            schedule
+                .bounds_at(starts_at - TimeDelta::seconds(1))
+                .is_none()
+        );
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn attribute_scores_to_current_season(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "Monthly", &pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board
+            .submit_score(user.id, Decimal::from(50), &pool)
+            .await?;
+
+        let starts_at = Utc::now() - TimeDelta::hours(1);
+        SeasonSchedule::set(&board, starts_at, SeasonLength::Month, &pool).await?;
+        let point = board.submit_score(user.id, Decimal::from(5), &pool).await?;
+        let season = Season::current(&board, &pool).await?.unwrap();
+        assert_eq!(point.season, Some(season.id));
+
+        // Points from before the season don't count
+        let query = StandingsQuery::default();
+        let standings = board
+            .standings(&query, &TimeWindow::default(), &pool)
+            .await?;
+        assert_eq!(standings[0].score, Decimal::from(5));
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn archiver_creates_seasons(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "Weekly", &pool).await?;
+        let starts_at = Utc::now() - TimeDelta::hours(1);
+        let schedule = SeasonSchedule::set(&board, starts_at, SeasonLength::Week, &pool).await?;
+
+        sqlx::query("DELETE FROM seasons").execute(&pool).await?;
+        assert!(Season::current(&board, &pool).await?.is_none());
+
+        assert_eq!(archive_ended_seasons(&pool).await?, 0);
+        let season = Season::current(&board, &pool).await?.unwrap();
+        assert_eq!(season.starts_at, schedule.starts_at);
+
+        // The next season exists but isn't listed until it starts
+        let count: i64 = sqlx::query_scalar("SELECT count(*) FROM seasons")
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(count, 2);
+        assert_eq!(Season::all(&board, &pool).await?.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test(migrations = "./migrations")]
+    async fn archive_ended_season(pool: PgPool) -> crate::Result<()> {
+        let board = Leaderboard::new(DEFAULT_PROJECT, "Daily", &pool).await?;
+        let user = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        let user2 = create_anon_user(DEFAULT_PROJECT, &pool).await?;
+        board.add_member(user.id, &pool).await?;
+        board.add_member(user2.id, &pool).await?;
+
+        let starts_at = Utc::now() - TimeDelta::hours(1);
+        SeasonSchedule::set(&board, starts_at, SeasonLength::Day, &pool).await?;
+        board
+            .submit_score(user.id, Decimal::from(10), &pool)
+            .await?;
+        board
+            .submit_score(user2.id, Decimal::from(30), &pool)
+            .await?;
+
+        let season = Season::current(&board, &pool).await?.unwrap();
+        assert!(
+            season
+                .standings(&StandingsQuery::default(), &pool)
+                .await?
+                .is_none()
+        );
+
+        sqlx::query("UPDATE seasons SET ends_at = now() WHERE id = $1")
+            .bind(season.id)
+            .execute(&pool)
+            .await?;
+        assert_eq!(archive_ended_seasons(&pool).await?, 1);
+        assert_eq!(archive_ended_seasons(&pool).await?, 0);
+
+        let season = Season::get(DEFAULT_PROJECT, season.id, &pool)
+            .await?
+            .unwrap();
+        let standings = season
+            .standings(&StandingsQuery::default(), &pool)
+            .await?
+            .unwrap();
+        let players: Vec<_> = standings.iter().map(|s| (s.rank, s.player)).collect();
+        assert_eq!(players, [(1, user2.id), (2, user.id)]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+//! Secret tokens such as session tokens and api keys.
+//!
+//! Tokens are generated from a cryptographically secure random number generator
+//! and only their SHA-256 hash is stored, the token itself is handed to the client
+//! once when it's created.
+//!
+//! Tokens are looked up by their hash, which doesn't leak anything about the
+//! stored token through timing since the hash of a guess can't be steered.
+//! Tokens checked against a known hash use [`verify`], which compares in
+//! constant time.
+use base64::engine::{Engine, general_purpose::URL_SAFE_NO_PAD};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+/// The number of random bytes in a token
+pub const TOKEN_BYTES: usize = 32;
+
+/// Generate a random url safe token from `n` random bytes.
+///
+/// ```
+/// use scoreboard::token;
+///
+/// let token = token::generate(32);
+/// assert_eq!(token.len(), 43);
+/// assert_ne!(token, token::generate(32));
+/// ```
+pub fn generate(n: usize) -> String {
+    let mut bytes = vec![0; n];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hash a token for storage, the hash is hex encoded.
+pub fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Check a token against a hash from [`hash`] in constant time.
+pub fn verify(token: &str, hash: &str) -> bool {
+    self::hash(token).as_bytes().ct_eq(hash.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_not_repeated_bytes() {
+        let token = URL_SAFE_NO_PAD.decode(generate(TOKEN_BYTES)).unwrap();
+        assert_eq!(token.len(), TOKEN_BYTES);
+        assert!(token.iter().any(|byte| *byte != token[0]));
+    }
+
+    #[test]
+    fn verify_hashed_token() {
+        let token = generate(TOKEN_BYTES);
+        let hash = hash(&token);
+        assert_ne!(hash, token);
+        assert!(verify(&token, &hash));
+        assert!(!verify(&generate(TOKEN_BYTES), &hash));
+    }
+}
@@ -0,0 +1,147 @@
+use chrono::{DateTime, Datelike, Days, Months, NaiveDate, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// The length of a [`TimeWindow`]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Period {
+    #[default]
+    AllTime,
+    Day,
+    Week,
+    Month,
+}
+
+/// A window of time that points are counted in, windows are either
+/// calendar based, e.g. "this week", or rolling, e.g. "the last 7 days".
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TimeWindow {
+    pub period: Period,
+    /// Count back from the current time instead of from the start
+    /// of the calendar period
+    pub rolling: bool,
+    /// The timezone calendar periods start in
+    pub timezone: Tz,
+}
+
+impl Default for TimeWindow {
+    fn default() -> Self {
+        Self {
+            period: Period::default(),
+            rolling: false,
+            timezone: Tz::UTC,
+        }
+    }
+}
+
+impl TimeWindow {
+    /// Create a calendar window in UTC
+    pub fn calendar(period: Period) -> Self {
+        Self {
+            period,
+            ..Default::default()
+        }
+    }
+
+    /// Create a rolling window
+    pub fn rolling(period: Period) -> Self {
+        Self {
+            period,
+            rolling: true,
+            ..Default::default()
+        }
+    }
+
+    /// Get the start of the window relative to `now`, returns `None` for
+    /// all-time windows.
+    ///
+    /// ```
+    /// use chrono::{TimeZone, Utc};
+    /// use scoreboard::window::{Period, TimeWindow};
+    ///
+    /// // A wednesday
+    /// let now = Utc.with_ymd_and_hms(2025, 5, 14, 15, 30, 0).unwrap();
+    ///
+    /// let start = TimeWindow::calendar(Period::Week).start(now);
+    /// assert_eq!(start, Utc.with_ymd_and_hms(2025, 5, 12, 0, 0, 0).single());
+    ///
+    /// let start = TimeWindow::rolling(Period::Day).start(now);
+    /// assert_eq!(start, Utc.with_ymd_and_hms(2025, 5, 13, 15, 30, 0).single());
+    /// ```
+    pub fn start(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if self.rolling {
+            return match self.period {
+                Period::AllTime => None,
+                Period::Day => now.checked_sub_days(Days::new(1)),
+                Period::Week => now.checked_sub_days(Days::new(7)),
+                Period::Month => now.checked_sub_months(Months::new(1)),
+            };
+        }
+
+        let today = now.with_timezone(&self.timezone).date_naive();
+        let first_day = match self.period {
+            Period::AllTime => return None,
+            Period::Day => today,
+            Period::Week => today - Days::new(today.weekday().num_days_from_monday().into()),
+            Period::Month => today.with_day(1)?,
+        };
+
+        Some(self.start_of_day(first_day))
+    }
+
+    /// Get the first instant of `date` in the window's timezone
+    fn start_of_day(&self, date: NaiveDate) -> DateTime<Utc> {
+        let mut time = date.and_time(Default::default());
+
+        // Midnight can be skipped when daylight saving time starts,
+        // in which case the day starts at the end of the gap.
+        loop {
+            if let Some(start) = self.timezone.from_local_datetime(&time).earliest() {
+                return start.with_timezone(&Utc);
+            }
+            time += TimeDelta::minutes(15);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_of_month_in_timezone() {
+        let window = TimeWindow {
+            period: Period::Month,
+            rolling: false,
+            timezone: Tz::Asia__Tokyo,
+        };
+
+        // Already the 1st of June in Tokyo
+        let now = Utc.with_ymd_and_hms(2025, 5, 31, 20, 0, 0).unwrap();
+        let start = Utc.with_ymd_and_hms(2025, 5, 31, 15, 0, 0).single();
+        assert_eq!(window.start(now), start);
+    }
+
+    #[test]
+    fn start_of_day_skipped_by_daylight_saving() {
+        let window = TimeWindow {
+            period: Period::Day,
+            rolling: false,
+            timezone: Tz::America__Santiago,
+        };
+
+        // Clocks in Santiago jumped from 00:00 to 01:00 on the 7th of September
+        let now = Utc.with_ymd_and_hms(2025, 9, 7, 18, 0, 0).unwrap();
+        let start = Utc.with_ymd_and_hms(2025, 9, 7, 4, 0, 0).single();
+        assert_eq!(window.start(now), start);
+    }
+
+    #[test]
+    fn all_time_has_no_start() {
+        let now = Utc::now();
+        assert_eq!(TimeWindow::default().start(now), None);
+        assert_eq!(TimeWindow::rolling(Period::AllTime).start(now), None);
+    }
+}
@@ -1,65 +1,660 @@
+use crate::api_key::Scope;
+use crate::auth::Caller;
+use crate::board::Leaderboard;
 use crate::db::ScoreBoard;
-use crate::{AppState, ClientMessage, ClientResponse};
-use crate::{ClientError, Error, Result};
+use crate::hub::{self, BoardEvent, Hub, Replay};
+use crate::project::{ProjectId, project_header};
+use crate::role::Permission;
+use crate::{AppState, ClientMessage, ClientResponse, Envelope, MessageId};
+use crate::{ClientError, ClientErrorKind, Error, Result};
+use axum::body::Bytes;
 use axum::{
     extract::{
-        State, WebSocketUpgrade,
-        ws::WebSocket,
+        Query, State, WebSocketUpgrade,
+        ws::{CloseFrame, Message, WebSocket},
     },
+    http::HeaderMap,
     response::Response,
 };
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use std::{
+    collections::HashMap,
+    env,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        broadcast::{
+            self,
+            error::{RecvError, TryRecvError},
+        },
+        mpsc,
+    },
+    task::{JoinHandle, JoinSet},
+    time,
+};
+use uuid::Uuid;
+
+/// How long a connection made without credentials has to send an
+/// [`ClientMessage::Authenticate`] message.
+pub const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The number of requests a connection can have in flight, reading
+/// pauses until one of them completes.
+const MAX_CONCURRENT_REQUESTS: usize = 16;
+
+/// How long requests in flight get to finish once the connection ends
+/// before they're aborted
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The subprotocol clients negotiate to exchange MessagePack encoded
+/// binary frames instead of JSON text frames
+pub const MSGPACK_PROTOCOL: &str = "msgpack";
+
+/// How the messages of a connection are encoded, both encodings share
+/// the same serde model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// JSON in text frames
+    #[default]
+    Json,
+    /// MessagePack in binary frames, structs are encoded as maps
+    MessagePack,
+}
+
+impl Encoding {
+    /// Encode a value into a frame
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Message> {
+        match self {
+            Self::Json => Ok(Message::Text(serde_json::to_string(value)?.into())),
+            Self::MessagePack => Ok(Message::Binary(rmp_serde::to_vec_named(value)?.into())),
+        }
+    }
+
+    /// Decode a frame, frames of the other encoding are rejected
+    pub fn decode<T: DeserializeOwned>(self, message: &Message) -> Result<T> {
+        match (self, message) {
+            (Self::Json, Message::Text(text)) => Ok(serde_json::from_str(text)?),
+            (Self::MessagePack, Message::Binary(bytes)) => Ok(rmp_serde::from_slice(bytes)?),
+            (Self::Json, _) => {
+                let error = ClientError::new(
+                    "Binary frames require the msgpack protocol",
+                    ClientErrorKind::InvalidInput,
+                );
+                Err(error.into())
+            }
+            (Self::MessagePack, _) => {
+                let error = ClientError::new(
+                    "The msgpack protocol only accepts binary frames",
+                    ClientErrorKind::InvalidInput,
+                );
+                Err(error.into())
+            }
+        }
+    }
+}
+
+/// The close code sent to connections that were idle for longer than
+/// the [`SocketConfig::idle_timeout`].
+pub const CLOSE_IDLE_TIMEOUT: u16 = 4000;
+
+/// How the server keeps websocket connections alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketConfig {
+    /// How often connections are pinged
+    pub ping_interval: Duration,
+    /// Connections that don't send anything, including pongs, for this
+    /// long are closed.
+    pub idle_timeout: Duration,
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(90),
+        }
+    }
+}
+
+impl SocketConfig {
+    /// Read the config from the `WS_PING_INTERVAL` and `WS_IDLE_TIMEOUT`
+    /// environment variables in seconds, missing or invalid values use
+    /// the defaults.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let seconds = |name: &str| {
+            env::var(name)
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs)
+        };
+
+        Self {
+            ping_interval: seconds("WS_PING_INTERVAL").unwrap_or(default.ping_interval),
+            idle_timeout: seconds("WS_IDLE_TIMEOUT").unwrap_or(default.idle_timeout),
+        }
+    }
+}
+
+/// For clients that can't set headers on the upgrade request, such as
+/// browsers.
+#[derive(Debug, Deserialize, Default)]
+pub struct SocketQuery {
+    /// A session token or API key
+    pub token: Option<String>,
+}
+
+/// Upgrade to a websocket connection. Connections are authenticated with
+/// a session or API key in the headers or the `token` query parameter, or
+/// with an [`ClientMessage::Authenticate`] message. Requests with invalid
+/// credentials are rejected and unauthenticated connections are read-only.
+///
+/// Messages are encoded as JSON unless the client asks for the
+/// [`MSGPACK_PROTOCOL`].
+pub async fn handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    caller: Option<Caller>,
+    Query(query): Query<SocketQuery>,
+) -> Result<Response> {
+    let caller = match (caller, query.token) {
+        (None, Some(token)) => Some(Caller::authenticate(&token, state.pool()).await?),
+        (caller, _) => caller,
+    };
+    let header = project_header(&headers)?;
+    let ProjectId(project) = ProjectId::resolve(caller.as_ref(), header, state.pool()).await?;
+
+    let ws = ws.protocols([MSGPACK_PROTOCOL]);
+    let encoding = match ws.selected_protocol() {
+        Some(protocol) if protocol == MSGPACK_PROTOCOL => Encoding::MessagePack,
+        _ => Encoding::Json,
+    };
+
+    let connection = Connection {
+        caller: Arc::new(caller),
+        encoding,
+        project,
+        project_header: header,
+        connected_at: Instant::now(),
+    };
+
+    Ok(ws.on_upgrade(move |socket| async move {
+        if let Err(error) = handle_socket(socket, connection, state).await {
+            tracing::warn!("Websocket connection closed with an error: {error}");
+        }
+    }))
+}
+
+/// Who a connection is made by and the project it's in
+struct Connection {
+    caller: Arc<Option<Caller>>,
+    encoding: Encoding,
+    project: Uuid,
+    project_header: Option<Uuid>,
+    connected_at: Instant,
+}
+
+/// A frame queued for the connection's writer
+enum Outgoing {
+    Response(Envelope<ClientResponse>),
+    Event(BoardEvent),
+    Ping,
+    Close(CloseFrame),
+}
+
+impl From<Envelope<ClientResponse>> for Outgoing {
+    fn from(response: Envelope<ClientResponse>) -> Self {
+        Self::Response(response)
+    }
+}
+
+/// Read messages from the socket until it's closed, responses are
+/// written to the socket by a separate task so that reading isn't
+/// blocked by slow clients. Requests are handled concurrently and
+/// responses are sent in the order they complete, subscriptions are
+/// handled in order since they belong to the connection.
+///
+/// Connections are pinged every [`SocketConfig::ping_interval`] and
+/// closed with [`CLOSE_IDLE_TIMEOUT`] once they've been idle for the
+/// [`SocketConfig::idle_timeout`]. Requests still in flight when the
+/// connection ends get up to [`DRAIN_TIMEOUT`] to finish.
+async fn handle_socket(
+    socket: WebSocket,
+    mut connection: Connection,
+    state: AppState,
+) -> Result<()> {
+    let config = *state.socket_config();
+    let encoding = connection.encoding;
+    let (mut sender, mut receiver) = socket.split();
+    let (tx, mut rx) = mpsc::channel::<Outgoing>(32);
+    let mut subscriptions = Subscriptions::new(tx.clone());
+    let mut requests = JoinSet::new();
+    let mut first_message = true;
+
+    let writer: JoinHandle<Result<()>> = tokio::spawn(async move {
+        while let Some(frame) = rx.recv().await {
+            let message = match frame {
+                Outgoing::Response(response) => encoding.encode(&response)?,
+                Outgoing::Event(event) => encoding.encode(&event)?,
+                Outgoing::Ping => Message::Ping(Bytes::new()),
+                Outgoing::Close(frame) => {
+                    sender.send(Message::Close(Some(frame))).await?;
+                    break;
+                }
+            };
+            sender.send(message).await?;
+        }
+        Ok(())
+    });
 
-pub async fn handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    ws.on_upgrade(async |socket| {
-        match handle_socket(socket, state).await {
-            Ok(_) => {}
-            Err(_) => {} // FIXME
+    let mut heartbeat = time::interval_at(
+        time::Instant::now() + config.ping_interval,
+        config.ping_interval,
+    );
+    let idle = time::sleep(config.idle_timeout);
+    tokio::pin!(idle);
+
+    let result = loop {
+        let message = tokio::select! {
+            message = receiver.next() => message,
+            _ = heartbeat.tick() => {
+                if tx.send(Outgoing::Ping).await.is_err() {
+                    break Ok(());
+                }
+                continue;
+            }
+            _ = &mut idle => {
+                let frame = CloseFrame {
+                    code: CLOSE_IDLE_TIMEOUT,
+                    reason: "Idle timeout".into(),
+                };
+                let _ = tx.send(Outgoing::Close(frame)).await;
+                break Ok(());
+            }
+        };
+
+        // Any frame, including pongs, keeps the connection alive
+        idle.as_mut()
+            .reset(time::Instant::now() + config.idle_timeout);
+
+        let frame = match message {
+            Some(Ok(frame @ (Message::Text(_) | Message::Binary(_)))) => frame,
+            Some(Ok(Message::Close(_))) | None => break Ok(()),
+            Some(Ok(_)) => continue,
+            Some(Err(error)) => break Err(Error::from(error)),
         };
-    })
-}
-
-async fn handle_socket(_socket: WebSocket, _state: AppState) -> crate::Result<()> {
-    // let (mut sender, mut receiver) = socket.split();
-    // let (tx, mut rx) = tokio::sync::mpsc::channel::<ClientMessage>(32);
-    // let mut group = ConnectionGroup::new();
-    // group.add_connection(tx);
-
-    // // Spawn a task to handle multiple messages
-    // let task: JoinHandle<Result<()>> = tokio::task::spawn(async move {
-    //     while let Some(message) = rx.recv().await {
-    //         let response = handle_message(message, &mut state).await?;
-    //         let message = serde_json::to_string(&response)?;
-    //         sender.send(Message::Text(message.into())).await?;
-    //     }
-    //     Ok(())
-    // });
-
-    // while let Some(msg) = receiver.next().await {
-    //     if let Message::Text(text) = msg? {
-    //         match serde_json::from_str::<ClientMessage>(&text) {
-    //             Ok(_) => {
-    //                 // tx.send(message).await;
-    //             }
-    //             Err(err) => {
-    //                 dbg!(err);
-    //             }
-    //         }
-    //     }
-    // }
-
-    // FIXME handle error
-    // let _ = task.await;
-
-    Ok(())
+
+        // Remove finished requests so the set doesn't keep growing
+        while requests.try_join_next().is_some() {}
+
+        let first = std::mem::replace(&mut first_message, false);
+        let project = connection.project;
+        let mut subscription = None;
+        let (id, response) = match encoding.decode::<Envelope<ClientMessage>>(&frame) {
+            Ok(Envelope {
+                id,
+                message: ClientMessage::Authenticate { token },
+            }) => (
+                id,
+                authenticate(&token, first, &mut connection, &state).await,
+            ),
+            Ok(Envelope {
+                id,
+                message: ClientMessage::Subscribe { board },
+            }) => {
+                let response = subscribe(board, project, &state).await;
+                (id, started(response, &mut subscription))
+            }
+            Ok(Envelope {
+                id,
+                message: ClientMessage::Resume { board, last_seq },
+            }) => {
+                let response = resume(board, last_seq, project, &state).await;
+                (id, started(response, &mut subscription))
+            }
+            Ok(Envelope {
+                id,
+                message: ClientMessage::Unsubscribe { board },
+            }) => {
+                subscriptions.remove(board, state.hub()).await;
+                (id, Ok(ClientResponse::Unsubscribed { board }))
+            }
+            Ok(Envelope { id, message }) => {
+                if requests.len() >= MAX_CONCURRENT_REQUESTS {
+                    requests.join_next().await;
+                }
+
+                let tx = tx.clone();
+                let caller = connection.caller.clone();
+                let mut state = state.clone();
+                requests.spawn(async move {
+                    let response =
+                        handle_message(message, caller.as_ref().as_ref(), project, &mut state)
+                            .await;
+                    // The connection was closed if sending fails
+                    let _ = tx.send(reply(id, response).into()).await;
+                });
+                continue;
+            }
+            Err(error) => {
+                let id = encoding
+                    .decode::<MessageHeader>(&frame)
+                    .ok()
+                    .and_then(|header| header.id);
+                let message = format!("Invalid message: {error}");
+                let error = ClientError::new(&message, ClientErrorKind::InvalidInput);
+                (id, Err(error.into()))
+            }
+        };
+
+        // The writer only stops when the socket is closed
+        if tx.send(reply(id, response).into()).await.is_err() {
+            break Ok(());
+        }
+
+        // Events are forwarded once the response was sent so they arrive after it
+        if let Some(subscription) = subscription {
+            subscriptions.add(subscription);
+        }
+    };
+
+    // Changes the client asked for before leaving are still made
+    let drain = async { while requests.join_next().await.is_some() {} };
+    if time::timeout(DRAIN_TIMEOUT, drain).await.is_err() {
+        tracing::warn!("Aborting {} unfinished websocket requests", requests.len());
+    }
+    drop(requests);
+    subscriptions.clear(state.hub()).await;
+    drop(subscriptions);
+    drop(tx);
+    let written = writer.await.unwrap_or(Ok(()));
+    result.and(written)
+}
+
+/// Authenticate a connection made without credentials, it has to be
+/// the first message and sent within the [`AUTH_TIMEOUT`].
+async fn authenticate(
+    token: &str,
+    first: bool,
+    connection: &mut Connection,
+    state: &AppState,
+) -> Result<ClientResponse> {
+    if connection.caller.is_some() {
+        let error = ClientError::new(
+            "The connection is already authenticated",
+            ClientErrorKind::Conflict,
+        );
+        return Err(error.into());
+    }
+
+    if !first || connection.connected_at.elapsed() > AUTH_TIMEOUT {
+        let error = ClientError::new(
+            "Connections have to authenticate with their first message",
+            ClientErrorKind::Unauthorized,
+        );
+        return Err(error.into());
+    }
+
+    let caller = Caller::authenticate(token, state.pool()).await?;
+    let ProjectId(project) =
+        ProjectId::resolve(Some(&caller), connection.project_header, state.pool()).await?;
+    let user = match &caller {
+        Caller::User(current) => Some(current.user.id),
+        Caller::ApiKey(_) => None,
+    };
+
+    connection.caller = Arc::new(Some(caller));
+    connection.project = project;
+    Ok(ClientResponse::Authenticated { user, project })
+}
+
+/// The fields shared by every message, used to find the id of
+/// messages that can't be parsed.
+#[derive(Deserialize)]
+struct MessageHeader {
+    #[serde(default)]
+    id: Option<MessageId>,
+}
+
+/// Build the response to a request, errors are reported to the client
+/// and the connection is kept open.
+fn reply(id: Option<MessageId>, response: Result<ClientResponse>) -> Envelope<ClientResponse> {
+    let response = response.unwrap_or_else(|error| error_response(&error));
+    Envelope::new(id, response)
+}
+
+/// Log an error and build the frame reporting it to the client
+fn error_response(error: &Error) -> ClientResponse {
+    let trace_id = Uuid::now_v7();
+    tracing::warn!(%trace_id, "Failed to handle websocket message: {error}");
+
+    let error = error.client_error();
+    ClientResponse::Error {
+        kind: error.kind(),
+        message: error.to_string(),
+        trace_id,
+    }
+}
+
+/// A subscription that starts forwarding events once the response to
+/// the request that made it was sent.
+struct Subscription {
+    board: i32,
+    events: broadcast::Receiver<BoardEvent>,
+    /// Events up to this sequence number are skipped since the client
+    /// already has them
+    after: u64,
+}
+
+/// Keep the subscription started by a request until its response is sent
+fn started(
+    response: Result<(ClientResponse, Subscription)>,
+    pending: &mut Option<Subscription>,
+) -> Result<ClientResponse> {
+    let (response, subscription) = response?;
+    *pending = Some(subscription);
+    Ok(response)
+}
+
+/// Subscribe to a board in the project
+async fn subscribe(
+    board: i32,
+    project: Uuid,
+    state: &AppState,
+) -> Result<(ClientResponse, Subscription)> {
+    get_board(project, board, state).await?;
+
+    let events = state.hub().subscribe(project, board).await?;
+    let seq = state.hub().seq(project, board).await?;
+    let subscription = Subscription {
+        board,
+        events,
+        after: seq,
+    };
+
+    Ok((ClientResponse::Subscribed { board, seq }, subscription))
+}
+
+/// Subscribe to a board again after reconnecting, the response contains
+/// the events published after `last_seq` or tells the client to reload the
+/// board if some of them are no longer kept.
+async fn resume(
+    board: i32,
+    last_seq: u64,
+    project: Uuid,
+    state: &AppState,
+) -> Result<(ClientResponse, Subscription)> {
+    get_board(project, board, state).await?;
+
+    // Subscribe before reading the missed events so none are lost in between
+    let events = state.hub().subscribe(project, board).await?;
+    let (response, after) = match state.hub().replay(project, board, last_seq).await? {
+        Replay::Events(events) => {
+            let after = events.last().map_or(last_seq, |event| event.seq);
+            (ClientResponse::Resumed { board, events }, after)
+        }
+        Replay::ResyncRequired(seq) => (ClientResponse::ResyncRequired { board, seq }, seq),
+    };
+    let subscription = Subscription {
+        board,
+        events,
+        after,
+    };
+
+    Ok((response, subscription))
+}
+
+/// Drop the events a receiver has queued and get the sequence number of
+/// the latest one, `None` if the channel is closed.
+fn latest_seq(events: &mut broadcast::Receiver<BoardEvent>) -> Option<u64> {
+    let mut seq = None;
+    loop {
+        match events.try_recv() {
+            Ok(event) => seq = Some(event.seq),
+            Err(TryRecvError::Lagged(_)) => {}
+            Err(TryRecvError::Empty) => return seq,
+            Err(TryRecvError::Closed) => return None,
+        }
+    }
+}
+
+/// The boards a connection is subscribed to, each subscription forwards
+/// the board's events to the connection's writer until it's removed.
+struct Subscriptions {
+    tx: mpsc::Sender<Outgoing>,
+    tasks: HashMap<i32, JoinHandle<()>>,
+}
+
+impl Subscriptions {
+    fn new(tx: mpsc::Sender<Outgoing>) -> Self {
+        Self {
+            tx,
+            tasks: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, subscription: Subscription) {
+        let Subscription {
+            board,
+            mut events,
+            mut after,
+        } = subscription;
+        let tx = self.tx.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.seq <= after => {}
+                    Ok(event) => {
+                        if tx.send(Outgoing::Event(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(count)) => {
+                        tracing::warn!("Subscriber to board {board} missed {count} events");
+                        // The client reloads the board, so the events it
+                        // hasn't been sent yet are dropped
+                        let Some(seq) = latest_seq(&mut events) else {
+                            break;
+                        };
+                        after = seq;
+                        let response = ClientResponse::ResyncRequired { board, seq };
+                        if tx.send(Envelope::new(None, response).into()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        if let Some(previous) = self.tasks.insert(board, task) {
+            previous.abort();
+        }
+    }
+
+    /// Stop forwarding the events of a board, the board's channel is
+    /// removed from the hub once it has no subscribers left.
+    async fn remove(&mut self, board: i32, hub: &Hub) {
+        if let Some(task) = self.tasks.remove(&board) {
+            task.abort();
+            // Wait for the task to drop its receiver
+            let _ = task.await;
+            hub.prune(board).await;
+        }
+    }
+
+    /// Remove every subscription of the connection
+    async fn clear(&mut self, hub: &Hub) {
+        let boards: Vec<i32> = self.tasks.keys().copied().collect();
+        for board in boards {
+            self.remove(board, hub).await;
+        }
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
 }
 
+/// Handle a message sent by a client in the project, messages that
+/// change a leaderboard require a caller.
 pub async fn handle_message(
     message: ClientMessage,
+    caller: Option<&Caller>,
+    project: Uuid,
     state: &mut AppState,
 ) -> Result<ClientResponse> {
-    let redis = state.client();
+    let mut redis = state.client().project(project);
 
     match message {
+        ClientMessage::AddMember { board, player } => {
+            require_caller(caller, state)
+                .await?
+                .require(Permission::ManageMembers)?;
+            let board = get_board(project, board, state).await?;
+            board.add_member(player, state.pool()).await?;
+
+            Ok(ClientResponse::AddMember {
+                board: board.id,
+                player,
+            })
+        }
+        ClientMessage::DeleteMember { board, player } => {
+            require_caller(caller, state)
+                .await?
+                .require(Permission::ManageMembers)?;
+            let board = get_board(project, board, state).await?;
+            if !board.remove_member(player, state.pool()).await? {
+                return Err(ClientError::not_found("Member not found").into());
+            }
+
+            Ok(ClientResponse::DeleteMember {
+                board: board.id,
+                player,
+            })
+        }
+        ClientMessage::UpdateScore {
+            board,
+            player,
+            score,
+        } => {
+            let caller = require_caller(caller, state).await?;
+            caller.require_scope(Scope::SubmitScore)?;
+            let player = caller.score_player(player)?;
+            let board = get_board(project, board, state).await?;
+            let point = hub::submit_score(state.hub(), &board, player, score, state.pool()).await?;
+
+            Ok(ClientResponse::UpdateScore { point })
+        }
         ClientMessage::CreateScoreBoard => {
             let board = ScoreBoard::new();
             let id = board.id();
@@ -79,6 +674,172 @@ pub async fn handle_message(
                 Err(error.into())
             }
         },
-        _ => Err(Error::UnsupportedMethod),
+        ClientMessage::Authenticate { .. }
+        | ClientMessage::Subscribe { .. }
+        | ClientMessage::Resume { .. }
+        | ClientMessage::Unsubscribe { .. } => Err(Error::UnsupportedMethod),
+    }
+}
+
+/// Get the caller of the connection as it is now, returns a
+/// [`ClientErrorKind::Unauthorized`] error if the connection was made
+/// without credentials or they were revoked since. Connections outlive
+/// sessions and keys so they're checked again before every change.
+async fn require_caller(caller: Option<&Caller>, state: &AppState) -> Result<Caller> {
+    let Some(caller) = caller else {
+        let error = ClientError::new(
+            "Sign in to change leaderboards",
+            ClientErrorKind::Unauthorized,
+        );
+        return Err(error.into());
+    };
+
+    caller.refresh(state.pool()).await
+}
+
+/// Get a leaderboard in the project
+async fn get_board(project: Uuid, id: i32, state: &AppState) -> Result<Leaderboard> {
+    Leaderboard::get(project, id, state.pool())
+        .await?
+        .ok_or(ClientError::not_found("Leaderboard not found").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use serde_json::Value;
+
+    /// Encode and decode a value, comparing the JSON representation
+    /// since the messages don't implement `PartialEq`
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> Result<()> {
+        for encoding in [Encoding::Json, Encoding::MessagePack] {
+            let frame = encoding.encode(value)?;
+            let decoded: T = encoding.decode(&frame)?;
+            assert_eq!(
+                serde_json::to_value(&decoded)?,
+                serde_json::to_value(value)?
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_messages() -> Result<()> {
+        let messages = [
+            Envelope::new(
+                Some(MessageId::Number(1)),
+                ClientMessage::UpdateScore {
+                    board: 1,
+                    player: Some(Uuid::now_v7()),
+                    score: Decimal::new(1025, 2),
+                },
+            ),
+            Envelope::new(
+                Some(MessageId::Text(String::from("request"))),
+                ClientMessage::GetScoreBoard { id: Uuid::new_v4() },
+            ),
+            Envelope::new(None, ClientMessage::CreateScoreBoard),
+            Envelope::new(
+                None,
+                ClientMessage::Resume {
+                    board: 2,
+                    last_seq: 10,
+                },
+            ),
+        ];
+
+        for message in &messages {
+            round_trip(message)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_responses() -> Result<()> {
+        let event = BoardEvent {
+            seq: 3,
+            event: ClientResponse::ScoreUpdated {
+                board: 1,
+                player: Uuid::now_v7(),
+                value: Decimal::ONE,
+                score: Decimal::TEN,
+                rank: 1,
+            },
+        };
+        round_trip(&event)?;
+
+        let responses = [
+            Envelope::new(
+                Some(MessageId::Number(-4)),
+                ClientResponse::Error {
+                    kind: ClientErrorKind::NotFound,
+                    message: String::from("Leaderboard not found"),
+                    trace_id: Uuid::now_v7(),
+                },
+            ),
+            Envelope::new(
+                None,
+                ClientResponse::Resumed {
+                    board: 1,
+                    events: vec![event],
+                },
+            ),
+        ];
+
+        for response in &responses {
+            round_trip(response)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reject_frames_of_the_other_encoding() -> Result<()> {
+        let message = Envelope::new(None, ClientMessage::CreateScoreBoard);
+        let text = Encoding::Json.encode(&message)?;
+        let binary = Encoding::MessagePack.encode(&message)?;
+
+        assert!(matches!(binary, Message::Binary(_)));
+        assert!(
+            Encoding::MessagePack
+                .decode::<Envelope<ClientMessage>>(&text)
+                .is_err()
+        );
+        assert!(Encoding::Json.decode::<Value>(&binary).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lagging_subscribers_resync() -> Result<()> {
+        let (sender, events) = broadcast::channel(2);
+        let event = |seq| BoardEvent {
+            seq,
+            event: ClientResponse::Unsubscribed { board: 1 },
+        };
+        for seq in 1..=5 {
+            sender.send(event(seq)).unwrap();
+        }
+
+        let (tx, mut rx) = mpsc::channel(4);
+        let mut subscriptions = Subscriptions::new(tx);
+        subscriptions.add(Subscription {
+            board: 1,
+            events,
+            after: 0,
+        });
+        assert!(matches!(
+            rx.recv().await,
+            Some(Outgoing::Response(Envelope {
+                message: ClientResponse::ResyncRequired { board: 1, seq: 5 },
+                ..
+            }))
+        ));
+
+        sender.send(event(6)).unwrap();
+        assert!(matches!(
+            rx.recv().await,
+            Some(Outgoing::Event(BoardEvent { seq: 6, .. }))
+        ));
+        Ok(())
     }
 }
@@ -1,11 +1,19 @@
-use scoreboard::{AppState, ClientMessage, ClientResponse, handle_message};
+use rust_decimal::Decimal;
+use scoreboard::{
+    AppState, ClientErrorKind, ClientMessage, ClientResponse, Error,
+    auth::{self, Caller, CurrentUser},
+    board::Leaderboard,
+    handle_message,
+    project::DEFAULT_PROJECT,
+    role::Role,
+};
 use sqlx::PgPool;
 
 #[sqlx::test]
 async fn create_scoreboard(pool: PgPool) -> scoreboard::Result<()> {
-    let mut state = AppState::new().await?;
+    let mut state = AppState::with_pool(pool).await?;
     let message = ClientMessage::CreateScoreBoard;
-    let response = handle_message(message, &mut state).await?;
+    let response = handle_message(message, None, DEFAULT_PROJECT, &mut state).await?;
 
     assert!(matches!(
         response,
@@ -13,3 +21,89 @@ async fn create_scoreboard(pool: PgPool) -> scoreboard::Result<()> {
     ));
     Ok(())
 }
+
+/// Create a user with the role and sign them in
+async fn caller(state: &AppState, role: Role) -> scoreboard::Result<Caller> {
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let user = auth::set_role(DEFAULT_PROJECT, user.id, role, state.pool())
+        .await?
+        .unwrap();
+    let session = auth::Session::create(user.id, state.pool()).await?;
+
+    Ok(Caller::User(CurrentUser {
+        user,
+        session_id: session.id,
+    }))
+}
+
+#[sqlx::test]
+async fn manage_members_and_scores(pool: PgPool) -> scoreboard::Result<()> {
+    let mut state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+    let moderator = caller(&state, Role::Moderator).await?;
+    let player = caller(&state, Role::Player).await?;
+    let Caller::User(current) = &player else {
+        unreachable!()
+    };
+    let player_id = current.user.id;
+
+    let message = ClientMessage::AddMember {
+        board: board.id,
+        player: player_id,
+    };
+    let response = handle_message(message, Some(&moderator), DEFAULT_PROJECT, &mut state).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::AddMember { player, .. } if player == player_id
+    ));
+
+    let message = ClientMessage::UpdateScore {
+        board: board.id,
+        player: None,
+        score: Decimal::TEN,
+    };
+    let response = handle_message(message, Some(&player), DEFAULT_PROJECT, &mut state).await?;
+    let ClientResponse::UpdateScore { point } = response else {
+        panic!("Expected a point, got {response:?}");
+    };
+    assert_eq!(point.player, player_id);
+    assert_eq!(point.value, Decimal::TEN);
+
+    let message = ClientMessage::DeleteMember {
+        board: board.id,
+        player: player_id,
+    };
+    let response = handle_message(message, Some(&moderator), DEFAULT_PROJECT, &mut state).await?;
+    assert!(matches!(response, ClientResponse::DeleteMember { .. }));
+    assert!(board.get_member(player_id, state.pool()).await?.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn members_require_permission(pool: PgPool) -> scoreboard::Result<()> {
+    let mut state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+    let player = caller(&state, Role::Player).await?;
+    let Caller::User(current) = &player else {
+        unreachable!()
+    };
+
+    let message = ClientMessage::AddMember {
+        board: board.id,
+        player: current.user.id,
+    };
+    let result = handle_message(message.clone(), None, DEFAULT_PROJECT, &mut state).await;
+    assert!(matches!(
+        result,
+        Err(Error::ClientError(error)) if error.kind() == ClientErrorKind::Unauthorized
+    ));
+
+    let result = handle_message(message, Some(&player), DEFAULT_PROJECT, &mut state).await;
+    assert!(matches!(
+        result,
+        Err(Error::ClientError(error)) if error.kind() == ClientErrorKind::Forbidden
+    ));
+
+    Ok(())
+}
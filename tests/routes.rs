@@ -2,16 +2,33 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
-use scoreboard::{AppState, api, auth::User, board::Leaderboard, router};
+use rust_decimal::Decimal;
+use scoreboard::{
+    AppState, ClientError, ClientErrorKind, api,
+    api_key::{API_KEY_HEADER, ApiKey, Scope},
+    auth::{self, Session, User},
+    board::{
+        Aggregation, BoardOptions, Leaderboard, LeaderboardMember, PlayerWindow, Point, SortOrder,
+        Standing,
+    },
+    mail::MemoryMailer,
+    project::{DEFAULT_PROJECT, PROJECT_HEADER, Project},
+    role::Role,
+    router,
+    season::{self, Season, SeasonLength, SeasonSchedule},
+};
 use serde::{Serialize, de::DeserializeOwned};
 use sqlx::PgPool;
-use std::usize;
 use tower::ServiceExt;
+use uuid::Uuid;
 
 struct RouteTest<B> {
     method: String,
     uri: String,
     body: Option<B>,
+    token: Option<String>,
+    api_key: Option<String>,
+    project: Option<Uuid>,
 }
 
 impl<B> RouteTest<B>
@@ -23,6 +40,9 @@ where
             method: String::from("GET"),
             uri: String::from("/"),
             body: None,
+            token: None,
+            api_key: None,
+            project: None,
         }
     }
 
@@ -41,6 +61,21 @@ where
         self
     }
 
+    fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_owned());
+        self
+    }
+
+    fn api_key(mut self, key: &str) -> Self {
+        self.api_key = Some(key.to_owned());
+        self
+    }
+
+    fn project(mut self, project: Uuid) -> Self {
+        self.project = Some(project);
+        self
+    }
+
     async fn send<R>(self, state: AppState) -> scoreboard::Result<(StatusCode, R)>
     where
         R: DeserializeOwned,
@@ -54,22 +89,93 @@ where
             body = Body::from(json);
         }
 
-        let request = Request::builder()
+        let mut request = Request::builder()
             .method(self.method.as_str())
             .uri(self.uri)
-            .header("Content-Type", "application/json")
-            .body(body)?;
+            .header("Content-Type", "application/json");
+
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
 
-        let response = app.oneshot(request).await.unwrap();
+        if let Some(key) = &self.api_key {
+            request = request.header(API_KEY_HEADER, key);
+        }
+
+        if let Some(project) = &self.project {
+            request = request.header(PROJECT_HEADER, project.to_string());
+        }
+
+        let response = app.oneshot(request.body(body)?).await.unwrap();
         let status = response.status();
 
-        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        let mut bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+        if bytes.is_empty() {
+            bytes = "null".into();
+        }
         let body: R = serde_json::from_slice(&bytes)?;
 
         Ok((status, body))
     }
 }
 
+/// Sign up as an anonymous user and start a session
+async fn sign_in(state: &AppState) -> scoreboard::Result<(User, Session)> {
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    Ok((user, session))
+}
+
+/// Sign up with a new email in the default project
+async fn sign_up(state: &AppState) -> scoreboard::Result<api::AuthResponse> {
+    let payload = api::CredentialsPayload {
+        email: format!("{}@example.com", uuid::Uuid::now_v7()),
+        password: String::from("correct horse"),
+    };
+    let (status, response) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/sign-up")
+        .send::<api::AuthResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    Ok(response)
+}
+
+/// Sign up as a user with a role in the default project, the first user
+/// to sign up owns the project and gives the other users their roles.
+async fn sign_in_as(state: &AppState, role: Role) -> scoreboard::Result<(User, Session)> {
+    let response = sign_up(state).await?;
+    let email = response.user.email.clone().unwrap();
+    if role == Role::Owner {
+        let owner = auth::promote_owner(DEFAULT_PROJECT, &email, state.pool()).await?;
+        return Ok((owner.unwrap(), response.session));
+    }
+    if role == Role::Player {
+        return Ok((response.user, response.session));
+    }
+
+    let owner = sign_up(state).await?;
+    auth::promote_owner(
+        DEFAULT_PROJECT,
+        owner.user.email.as_deref().unwrap(),
+        state.pool(),
+    )
+    .await?;
+    let (status, user) = RouteTest::new()
+        .body(api::SetRolePayload { role })
+        .method("PUT")
+        .uri(&format!("/api/v1/users/{}/role", response.user.id))
+        .token(&owner.session.token)
+        .send::<User>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+
+    Ok((user, response.session))
+}
+
 #[sqlx::test]
 async fn sign_in_anonymously(pool: PgPool) -> scoreboard::Result<()> {
     let state = AppState::with_pool(pool).await?;
@@ -85,14 +191,466 @@ async fn sign_in_anonymously(pool: PgPool) -> scoreboard::Result<()> {
     assert_eq!(response.status(), StatusCode::CREATED);
 
     let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
-    let user: User = serde_json::from_slice(&bytes)?;
+    let response: api::AuthResponse = serde_json::from_slice(&bytes)?;
 
     let new_user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
-        .bind(user.id)
+        .bind(response.user.id)
         .fetch_one(state.pool())
         .await?;
 
     assert!(new_user.is_anonymous);
+    assert_eq!(response.session.user_id, new_user.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn sign_up_and_login(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let payload = api::CredentialsPayload {
+        email: String::from("player@example.com"),
+        password: String::from("correct horse"),
+    };
+
+    let (status, response) = RouteTest::new()
+        .body(&payload)
+        .method("POST")
+        .uri("/api/v1/auth/sign-up")
+        .send::<api::AuthResponse>(state.clone())
+        .await?;
+
+    let user = response.user;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(!user.is_anonymous);
+    assert!(user.encrypted_password.is_none());
+
+    let (status, error) = RouteTest::new()
+        .body(&payload)
+        .method("POST")
+        .uri("/api/v1/auth/sign-up")
+        .send::<ClientError>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error.kind(), ClientErrorKind::Conflict);
+
+    let (status, response) = RouteTest::new()
+        .body(&payload)
+        .method("POST")
+        .uri("/api/v1/auth/login")
+        .send::<api::AuthResponse>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.user.id, user.id);
+    assert_eq!(response.session.user_id, user.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn login_with_wrong_password(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    auth::create_user(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+
+    let payload = api::CredentialsPayload {
+        email: String::from("player@example.com"),
+        password: String::from("battery staple"),
+    };
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/login")
+        .send::<ClientError>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error.kind(), ClientErrorKind::InvalidCredentials);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_current_user_and_logout(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (user, session) = sign_in(&state).await?;
+
+    let (status, current) = RouteTest::<()>::new()
+        .uri("/api/v1/auth/user")
+        .token(&session.token)
+        .send::<User>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(current.id, user.id);
+
+    let (status, ()) = RouteTest::<()>::new()
+        .method("POST")
+        .uri("/api/v1/auth/logout")
+        .token(&session.token)
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, error) = RouteTest::<()>::new()
+        .uri("/api/v1/auth/user")
+        .token(&session.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error.kind(), ClientErrorKind::Unauthorized);
+
+    let (status, _) = RouteTest::<()>::new()
+        .uri("/api/v1/auth/user")
+        .send::<ClientError>(state)
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn upgrade_an_anonymous_user(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (user, session) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Board", state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+
+    let payload = api::UpgradePayload {
+        email: Some(String::from("player@example.com")),
+        password: String::from("correct horse"),
+        ..Default::default()
+    };
+    let (status, upgraded) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/upgrade")
+        .token(&session.token)
+        .send::<User>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upgraded.id, user.id);
+    assert!(!upgraded.is_anonymous);
+    assert!(board.get_member(user.id, state.pool()).await?.is_some());
+
+    let (user, _) = auth::login(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+    assert_eq!(user.id, upgraded.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn merge_an_anonymous_user(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let existing = auth::create_user(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+    let (user, session) = sign_in(&state).await?;
+
+    let payload = api::UpgradePayload {
+        email: Some(String::from("player@example.com")),
+        password: String::from("battery staple"),
+        ..Default::default()
+    };
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/upgrade")
+        .token(&session.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error.kind(), ClientErrorKind::Conflict);
+
+    let payload = api::CredentialsPayload {
+        email: String::from("player@example.com"),
+        password: String::from("correct horse"),
+    };
+    let (status, response) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/upgrade/merge")
+        .token(&session.token)
+        .send::<api::AuthResponse>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(response.user.id, existing.id);
+    assert_eq!(response.session.user_id, existing.id);
+    assert!(
+        Session::get_user(&session.token, state.pool())
+            .await?
+            .is_none()
+    );
+
+    let deleted: Option<User> = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_optional(state.pool())
+        .await?;
+    assert!(deleted.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn reset_a_password(pool: PgPool) -> scoreboard::Result<()> {
+    let mailer = MemoryMailer::default();
+    let state = AppState::with_pool(pool).await?.with_mailer(mailer.clone());
+    auth::create_user(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+
+    let payload = api::PasswordResetPayload {
+        email: String::from("player@example.com"),
+    };
+    let (status, ()) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/password-reset")
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let email = mailer.sent().pop().unwrap();
+    let payload = api::ResetPasswordPayload {
+        token: email.body.lines().nth(2).unwrap().to_owned(),
+        password: String::from("battery staple"),
+    };
+    let (status, ()) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/password-reset/confirm")
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    auth::login(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "battery staple",
+        state.pool(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn verify_an_email(pool: PgPool) -> scoreboard::Result<()> {
+    let mailer = MemoryMailer::default();
+    let state = AppState::with_pool(pool).await?.with_mailer(mailer.clone());
+    auth::create_user(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+    let (_, session) = auth::login(
+        DEFAULT_PROJECT,
+        "player@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+
+    let (status, ()) = RouteTest::<()>::new()
+        .method("POST")
+        .uri("/api/v1/auth/verify-email")
+        .token(&session.token)
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let email = mailer.sent().pop().unwrap();
+    let payload = api::VerifyEmailPayload {
+        token: email.body.lines().nth(2).unwrap().to_owned(),
+    };
+    let (status, user) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/verify-email/confirm")
+        .send::<User>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(user.email_verified_at.is_some());
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn create_a_project(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, owner) = sign_in_as(&state, Role::Owner).await?;
+    let (_, admin) = sign_in_as(&state, Role::Admin).await?;
+
+    let payload = api::CreateProjectPayload {
+        name: String::from("Second game"),
+        email: String::from("studio@example.com"),
+        password: String::from("correct horse"),
+    };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/projects")
+        .token(&admin.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let payload = api::CreateProjectPayload {
+        name: String::from("  "),
+        email: String::from("studio@example.com"),
+        password: String::from("correct horse"),
+    };
+    let (_, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/projects")
+        .token(&owner.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(error.kind(), ClientErrorKind::InvalidInput);
+
+    let payload = api::CreateProjectPayload {
+        name: String::from("Second game"),
+        email: String::from("studio@example.com"),
+        password: String::from("correct horse"),
+    };
+    let (status, created) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/projects")
+        .token(&owner.token)
+        .send::<api::CreateProjectResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(created.project.name, "Second game");
+    assert_eq!(created.owner.project, created.project.id);
+    assert_eq!(created.owner.role, Role::Owner);
+
+    let payload = api::CredentialsPayload {
+        email: String::from("studio@example.com"),
+        password: String::from("correct horse"),
+    };
+    let (status, auth) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/login")
+        .project(created.project.id)
+        .send::<api::AuthResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(auth.user.id, created.owner.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn projects_are_isolated(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let project = Project::create("Second game", state.pool()).await?;
+
+    let (status, auth) = RouteTest::<()>::new()
+        .method("POST")
+        .uri("/api/v1/auth/sign-up/anonymous")
+        .project(project.id)
+        .send::<api::AuthResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(auth.user.project, project.id);
+    auth::set_role(project.id, auth.user.id, Role::Admin, state.pool()).await?;
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("Second game board"),
+        ..Default::default()
+    };
+    let (status, board) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .token(&auth.session.token)
+        .send::<Leaderboard>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(board.project, project.id);
+
+    let (_, boards) = RouteTest::<()>::new()
+        .uri("/api/v1/leaderboards")
+        .send::<Vec<Leaderboard>>(state.clone())
+        .await?;
+    assert!(boards.is_empty());
+
+    let (_, boards) = RouteTest::<()>::new()
+        .uri("/api/v1/leaderboards")
+        .project(project.id)
+        .send::<Vec<Leaderboard>>(state.clone())
+        .await?;
+    assert_eq!(boards.len(), 1);
+
+    let (status, _) = RouteTest::<()>::new()
+        .uri(&format!("/api/v1/leaderboard/{}/standings", board.id))
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = RouteTest::<()>::new()
+        .uri("/api/v1/leaderboards")
+        .token(&auth.session.token)
+        .project(DEFAULT_PROJECT)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn submit_a_score_for_another_player(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in(&state).await?;
+    let other = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    board.add_member(other.id, state.pool()).await?;
+
+    let payload = api::SubmitScorePayload {
+        player: Some(other.id),
+        value: Decimal::ONE,
+    };
+
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .token(&session.token)
+        .send::<ClientError>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(error.kind(), ClientErrorKind::Forbidden);
 
     Ok(())
 }
@@ -100,14 +658,18 @@ async fn sign_in_anonymously(pool: PgPool) -> scoreboard::Result<()> {
 #[sqlx::test]
 async fn create_a_leaderboard(pool: PgPool) -> scoreboard::Result<()> {
     let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in_as(&state, Role::Admin).await?;
 
-    let mut payload = api::CreateBoardPayload::default();
-    payload.name = String::from("Leaderboard123");
+    let payload = api::CreateBoardPayload {
+        name: String::from("Leaderboard123"),
+        ..Default::default()
+    };
 
     let (status, leaderboard) = RouteTest::new()
         .body(payload)
         .method("POST")
         .uri("/api/v1/leaderboard")
+        .token(&session.token)
         .send::<Leaderboard>(state.clone())
         .await?;
 
@@ -119,6 +681,665 @@ async fn create_a_leaderboard(pool: PgPool) -> scoreboard::Result<()> {
         .await?;
 
     assert_eq!(new_board.name, "Leaderboard123");
+    assert_eq!(new_board.aggregation, Aggregation::Sum);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn players_cannot_create_leaderboards(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in(&state).await?;
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("Leaderboard123"),
+        ..Default::default()
+    };
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .token(&session.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(error.kind(), ClientErrorKind::Forbidden);
+
+    let payload = api::CreateBoardPayload::default();
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .send::<ClientError>(state)
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn moderate_a_leaderboard(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, moderator) = sign_in_as(&state, Role::Moderator).await?;
+    let (_, admin) = sign_in_as(&state, Role::Admin).await?;
+    let (player, _) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+
+    let payload = api::AddMemberPayload { player: player.id };
+    let (status, member) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/members", board.id))
+        .token(&moderator.token)
+        .send::<LeaderboardMember>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(member.player, player.id);
+    board
+        .submit_score(player.id, Decimal::TEN, state.pool())
+        .await?;
+
+    let payload = api::RenameBoardPayload {
+        name: String::from("Renamed"),
+    };
+    let (status, renamed) = RouteTest::new()
+        .body(payload)
+        .method("PATCH")
+        .uri(&format!("/api/v1/leaderboard/{}", board.id))
+        .token(&moderator.token)
+        .send::<Leaderboard>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(renamed.name, "Renamed");
+
+    let (status, ()) = RouteTest::<()>::new()
+        .method("DELETE")
+        .uri(&format!(
+            "/api/v1/leaderboard/{}/members/{}",
+            board.id, player.id
+        ))
+        .token(&moderator.token)
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let standings = board
+        .standings(&Default::default(), &Default::default(), state.pool())
+        .await?;
+    assert!(standings.is_empty());
+
+    let (status, _) = RouteTest::<()>::new()
+        .method("DELETE")
+        .uri(&format!("/api/v1/leaderboard/{}", board.id))
+        .token(&moderator.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, ()) = RouteTest::<()>::new()
+        .method("DELETE")
+        .uri(&format!("/api/v1/leaderboard/{}", board.id))
+        .token(&admin.token)
+        .send(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(
+        Leaderboard::get(DEFAULT_PROJECT, board.id, state.pool())
+            .await?
+            .is_none()
+    );
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn sign_ups_are_players(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (anon, anon_session) = sign_in(&state).await?;
+    assert_eq!(anon.role, Role::Player);
+
+    // Anyone can sign up to the default project, so nobody becomes its owner
+    let first = sign_up(&state).await?;
+    assert_eq!(first.user.role, Role::Player);
+    let second = sign_up(&state).await?;
+    assert_eq!(second.user.role, Role::Player);
+
+    let payload = api::UpgradePayload {
+        email: Some(String::from("anon@example.com")),
+        password: String::from("correct horse"),
+        ..Default::default()
+    };
+    let (status, upgraded) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/auth/upgrade")
+        .token(&anon_session.token)
+        .send::<User>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(upgraded.role, Role::Player);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn only_owners_set_roles(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (owner, owner_session) = sign_in_as(&state, Role::Owner).await?;
+    assert_eq!(owner.role, Role::Owner);
+    let (_, admin) = sign_in_as(&state, Role::Admin).await?;
+    let (player, _) = sign_in(&state).await?;
+
+    let payload = api::SetRolePayload {
+        role: Role::Moderator,
+    };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("PUT")
+        .uri(&format!("/api/v1/users/{}/role", player.id))
+        .token(&admin.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let payload = api::SetRolePayload {
+        role: Role::Moderator,
+    };
+    let (status, user) = RouteTest::new()
+        .body(payload)
+        .method("PUT")
+        .uri(&format!("/api/v1/users/{}/role", player.id))
+        .token(&owner_session.token)
+        .send::<User>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(user.role, Role::Moderator);
+
+    let payload = api::SetRolePayload { role: Role::Player };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("PUT")
+        .uri(&format!("/api/v1/users/{}/role", owner.id))
+        .token(&owner_session.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn create_a_best_score_leaderboard(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in_as(&state, Role::Admin).await?;
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("High score"),
+        options: BoardOptions {
+            aggregation: Aggregation::Best,
+            ..Default::default()
+        },
+    };
+
+    let (status, leaderboard) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .token(&session.token)
+        .send::<Leaderboard>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(leaderboard.aggregation, Aggregation::Best);
+    assert_eq!(leaderboard.sort_order, SortOrder::Descending);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn create_a_lowest_time_leaderboard(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in_as(&state, Role::Admin).await?;
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("Fastest lap"),
+        options: BoardOptions {
+            aggregation: Aggregation::Best,
+            sort_order: SortOrder::Ascending,
+        },
+    };
+
+    let (status, leaderboard) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .token(&session.token)
+        .send::<Leaderboard>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(leaderboard.aggregation, Aggregation::Best);
+    assert_eq!(leaderboard.sort_order, SortOrder::Ascending);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn submit_a_score(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (user, session) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+
+    let payload = api::SubmitScorePayload {
+        player: Some(user.id),
+        value: Decimal::new(12025, 2),
+    };
+
+    let (status, point) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .token(&session.token)
+        .send::<Point>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::CREATED);
+
+    let stored: Point = sqlx::query_as("SELECT * FROM points WHERE id = $1")
+        .bind(point.id)
+        .fetch_one(state.pool())
+        .await?;
+
+    assert_eq!(stored.value, Decimal::new(12025, 2));
+    assert_eq!(stored.player, user.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn submit_a_score_with_an_api_key(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let admin = auth::create_user(
+        DEFAULT_PROJECT,
+        "admin@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+    auth::set_role(DEFAULT_PROJECT, admin.id, Role::Admin, state.pool()).await?;
+    let (_, session) = auth::login(
+        DEFAULT_PROJECT,
+        "admin@example.com",
+        "correct horse",
+        state.pool(),
+    )
+    .await?;
+    let (player, _) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    board.add_member(player.id, state.pool()).await?;
+
+    let payload = api::CreateApiKeyPayload {
+        name: String::from("Game server"),
+        scopes: vec![Scope::SubmitScore],
+    };
+    let (status, created) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/api-keys")
+        .token(&session.token)
+        .send::<api::CreateApiKeyResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let payload = api::SubmitScorePayload {
+        player: Some(player.id),
+        value: Decimal::TEN,
+    };
+    let (status, point) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .api_key(&created.key)
+        .send::<Point>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(point.player, player.id);
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("Not allowed"),
+        ..Default::default()
+    };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .api_key(&created.key)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, keys) = RouteTest::<()>::new()
+        .uri("/api/v1/api-keys")
+        .token(&session.token)
+        .send::<Vec<ApiKey>>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(keys.len(), 1);
+
+    let (status, revoked) = RouteTest::<()>::new()
+        .method("DELETE")
+        .uri(&format!("/api/v1/api-keys/{}", created.api_key.id))
+        .token(&session.token)
+        .send::<ApiKey>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert!(revoked.revoked_at.is_some());
+
+    let payload = api::SubmitScorePayload {
+        player: Some(player.id),
+        value: Decimal::TEN,
+    };
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .api_key(&created.key)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error.kind(), ClientErrorKind::Unauthorized);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn manage_boards_with_an_api_key(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (admin, session) = sign_in_as(&state, Role::Admin).await?;
+
+    let payload = api::CreateApiKeyPayload {
+        name: String::from("Admin tool"),
+        scopes: vec![Scope::ManageBoards],
+    };
+    let (status, created) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/api-keys")
+        .token(&session.token)
+        .send::<api::CreateApiKeyResponse>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let payload = api::CreateBoardPayload {
+        name: String::from("Created by a key"),
+        ..Default::default()
+    };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .api_key(&created.key)
+        .send::<Leaderboard>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // The key stops working once its creator can't manage keys
+    auth::set_role(DEFAULT_PROJECT, admin.id, Role::Moderator, state.pool()).await?;
+    let payload = api::CreateBoardPayload {
+        name: String::from("Created by a key"),
+        ..Default::default()
+    };
+    let (status, _) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/leaderboard")
+        .api_key(&created.key)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn players_cannot_create_api_keys(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = sign_in_as(&state, Role::Player).await?;
+
+    let payload = api::CreateApiKeyPayload {
+        name: String::from("Game server"),
+        scopes: vec![Scope::SubmitScore],
+    };
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri("/api/v1/api-keys")
+        .token(&session.token)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(error.kind(), ClientErrorKind::Forbidden);
+
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM api_keys")
+        .fetch_one(state.pool())
+        .await?;
+    assert_eq!(count, 0);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn submit_a_score_as_non_member(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (user, session) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+
+    let payload = api::SubmitScorePayload {
+        player: Some(user.id),
+        value: Decimal::new(12025, 2),
+    };
+
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .token(&session.token)
+        .send::<ClientError>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(error.kind(), ClientErrorKind::Forbidden);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_dense_standings(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+
+    for score in [50, 50, 10] {
+        let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+        board.add_member(user.id, state.pool()).await?;
+        board
+            .submit_score(user.id, score.into(), state.pool())
+            .await?;
+    }
+
+    let (status, standings) = RouteTest::<()>::new()
+        .uri(&format!(
+            "/api/v1/leaderboard/{}/standings?mode=dense&limit=2&offset=1",
+            board.id
+        ))
+        .send::<Vec<Standing>>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    let ranks: Vec<i64> = standings.iter().map(|s| s.rank).collect();
+    assert_eq!(ranks, [1, 2]);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_standings_around_player(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+
+    let mut players = vec![];
+    for score in [40, 30, 20, 10] {
+        let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+        board.add_member(user.id, state.pool()).await?;
+        board
+            .submit_score(user.id, score.into(), state.pool())
+            .await?;
+        players.push(user.id);
+    }
+
+    let (status, window) = RouteTest::<()>::new()
+        .uri(&format!(
+            "/api/v1/leaderboard/{}/players/{}/around?count=1",
+            board.id, players[2]
+        ))
+        .send::<PlayerWindow>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(window.rank, 3);
+    assert_eq!(window.standings.len(), 3);
+
+    let (status, error) = RouteTest::<()>::new()
+        .uri(&format!(
+            "/api/v1/leaderboard/{}/players/{}/around",
+            board.id,
+            uuid::Uuid::new_v4()
+        ))
+        .send::<ClientError>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error.kind(), ClientErrorKind::NotFound);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn submit_an_out_of_range_score(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (user, session) = sign_in(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+
+    let payload = api::SubmitScorePayload {
+        player: Some(user.id),
+        value: Decimal::new(-10_000_000, 0),
+    };
+
+    let (status, error) = RouteTest::new()
+        .body(payload)
+        .method("POST")
+        .uri(&format!("/api/v1/leaderboard/{}/scores", board.id))
+        .token(&session.token)
+        .send::<ClientError>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error.kind(), ClientErrorKind::InvalidScore);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_weekly_standings(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let user2 = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+    board.add_member(user2.id, state.pool()).await?;
+
+    sqlx::query(
+        "INSERT INTO points(leaderboard,value,player,created_at) 
+        VALUES($1,500,$2,now() - INTERVAL '40 days')",
+    )
+    .bind(board.id)
+    .bind(user.id)
+    .execute(state.pool())
+    .await?;
+    board
+        .submit_score(user2.id, Decimal::from(10), state.pool())
+        .await?;
+
+    let (status, standings) = RouteTest::<()>::new()
+        .uri(&format!(
+            "/api/v1/leaderboard/{}/standings?period=week&timezone=Pacific/Auckland",
+            board.id
+        ))
+        .send::<Vec<Standing>>(state)
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings.len(), 1);
+    assert_eq!(standings[0].player, user2.id);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn schedule_and_archive_seasons(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+    let (_, session) = sign_in_as(&state, Role::Admin).await?;
+
+    let payload = api::SeasonSchedulePayload {
+        starts_at: chrono::Utc::now(),
+        length: SeasonLength::Week,
+    };
+    let (status, schedule) = RouteTest::new()
+        .body(payload)
+        .method("PUT")
+        .uri(&format!("/api/v1/leaderboard/{}/season-schedule", board.id))
+        .token(&session.token)
+        .send::<SeasonSchedule>(state.clone())
+        .await?;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(schedule.length, SeasonLength::Week);
+
+    let point = board
+        .submit_score(user.id, Decimal::from(75), state.pool())
+        .await?;
+
+    let (_, seasons) = RouteTest::<()>::new()
+        .uri(&format!("/api/v1/leaderboard/{}/seasons", board.id))
+        .send::<Vec<Season>>(state.clone())
+        .await?;
+
+    assert_eq!(seasons.len(), 1);
+    assert_eq!(point.season, Some(seasons[0].id));
+
+    let uri = format!("/api/v1/seasons/{}/standings", seasons[0].id);
+    let (status, _) = RouteTest::<()>::new()
+        .uri(&uri)
+        .send::<ClientError>(state.clone())
+        .await?;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    sqlx::query("UPDATE seasons SET ends_at = now()")
+        .execute(state.pool())
+        .await?;
+    season::archive_ended_seasons(state.pool()).await?;
+
+    let (status, standings) = RouteTest::<()>::new()
+        .uri(&uri)
+        .send::<Vec<Standing>>(state)
+        .await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(standings[0].player, user.id);
+    assert_eq!(standings[0].score, Decimal::from(75));
 
     Ok(())
 }
@@ -2,11 +2,65 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
-use scoreboard::{AppState, auth::User, router};
+use futures_util::{SinkExt, StreamExt};
+use rust_decimal::Decimal;
+use scoreboard::{
+    AppState, ClientErrorKind, ClientMessage, ClientResponse, Envelope, MessageId,
+    api::{AuthResponse, SubmitScorePayload},
+    auth::{self, Session, User},
+    board::Leaderboard,
+    hub::{self, BoardEvent},
+    project::{DEFAULT_PROJECT, Project},
+    role::Role,
+    router,
+    ws::{CLOSE_IDLE_TIMEOUT, MSGPACK_PROTOCOL, SocketConfig},
+};
 use sqlx::PgPool;
-use std::usize;
-use tokio_tungstenite::connect_async;
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
+use tokio::{net::TcpStream, time};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{Error, client::IntoClientRequest, protocol::Message},
+};
 use tower::ServiceExt;
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Serve the app on an unused port
+async fn serve(state: AppState) -> scoreboard::Result<SocketAddr> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, router(state)).await });
+
+    Ok(address)
+}
+
+/// Send a message and wait for the response
+async fn send(socket: &mut Socket, message: &ClientMessage) -> scoreboard::Result<ClientResponse> {
+    let text = serde_json::to_string(message)?;
+    socket.send(Message::Text(text.into())).await?;
+
+    receive(socket).await
+}
+
+/// Wait for the next text message from the server
+async fn receive(socket: &mut Socket) -> scoreboard::Result<ClientResponse> {
+    loop {
+        if let Message::Text(text) = next_message(socket).await? {
+            return Ok(serde_json::from_str(&text)?);
+        }
+    }
+}
+
+/// Wait for the next frame from the server, returns an error if the
+/// connection ended.
+async fn next_message(socket: &mut Socket) -> scoreboard::Result<Message> {
+    match socket.next().await {
+        Some(message) => Ok(message?),
+        None => Err(Error::ConnectionClosed.into()),
+    }
+}
 
 #[sqlx::test]
 async fn connect_to_web_socket(pool: PgPool) -> scoreboard::Result<()> {
@@ -23,8 +77,9 @@ async fn connect_to_web_socket(pool: PgPool) -> scoreboard::Result<()> {
     assert_eq!(response.status(), StatusCode::CREATED);
 
     let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
-    let user: User = serde_json::from_slice(&bytes)?;
-    connect_async("http://localhost:5000/ws").await?;
+    let AuthResponse { user, .. } = serde_json::from_slice(&bytes)?;
+    let address = serve(state.clone()).await?;
+    connect_async(format!("ws://{address}/ws")).await?;
 
     let new_user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
         .bind(user.id)
@@ -35,3 +90,638 @@ async fn connect_to_web_socket(pool: PgPool) -> scoreboard::Result<()> {
 
     Ok(())
 }
+
+#[sqlx::test]
+async fn create_and_get_a_scoreboard(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    let response = send(&mut socket, &ClientMessage::CreateScoreBoard).await?;
+    let ClientResponse::CreateScoreBoard { id } = response else {
+        panic!("Unexpected response {response:?}");
+    };
+
+    let response = send(&mut socket, &ClientMessage::GetScoreBoard { id }).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::GetScoreBoard { scoreboard } if scoreboard.id() == id
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn keep_serving_after_a_bad_message(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    for text in ["not json", r#"{"method":"unknown"}"#] {
+        socket.send(Message::Text(text.into())).await?;
+        let response = receive(&mut socket).await?;
+        assert!(matches!(
+            response,
+            ClientResponse::Error {
+                kind: ClientErrorKind::InvalidInput,
+                ..
+            }
+        ));
+    }
+
+    let response = send(&mut socket, &ClientMessage::CreateScoreBoard).await?;
+    assert!(matches!(response, ClientResponse::CreateScoreBoard { .. }));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn report_errors_to_the_client(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    let message = ClientMessage::GetScoreBoard { id: Uuid::now_v7() };
+    let response = send(&mut socket, &message).await?;
+    let ClientResponse::Error {
+        kind,
+        message,
+        trace_id,
+    } = response
+    else {
+        panic!("Expected an error, got {response:?}");
+    };
+    assert_eq!(kind, ClientErrorKind::NotFound);
+    assert_eq!(message, "Scoreboard not found");
+    assert_eq!(trace_id.get_version_num(), 7);
+
+    // The connection stays open
+    let response = send(&mut socket, &ClientMessage::CreateScoreBoard).await?;
+    assert!(matches!(response, ClientResponse::CreateScoreBoard { .. }));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn echo_request_ids(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    // Send every request before reading the responses
+    for id in 1..=3 {
+        let message = Envelope::new(Some(MessageId::Number(id)), ClientMessage::CreateScoreBoard);
+        let text = serde_json::to_string(&message)?;
+        socket.send(Message::Text(text.into())).await?;
+    }
+    socket
+        .send(Message::Text(r#"{"id":"bad","method":"unknown"}"#.into()))
+        .await?;
+
+    let mut ids = HashSet::new();
+    while ids.len() < 4 {
+        let Message::Text(text) = next_message(&mut socket).await? else {
+            continue;
+        };
+        let response: Envelope<ClientResponse> = serde_json::from_str(&text)?;
+        match response.id {
+            Some(MessageId::Text(_)) => {
+                assert!(matches!(response.message, ClientResponse::Error { .. }))
+            }
+            _ => assert!(matches!(
+                response.message,
+                ClientResponse::CreateScoreBoard { .. }
+            )),
+        }
+        ids.insert(response.id.unwrap());
+    }
+
+    assert!(ids.contains(&MessageId::Text("bad".to_owned())));
+    assert!((1..=3).all(|id| ids.contains(&MessageId::Number(id))));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn push_score_updates_to_subscribers(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard123", state.pool()).await?;
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+
+    let address = serve(state.clone()).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+    let response = send(&mut socket, &ClientMessage::Subscribe { board: board.id }).await?;
+    assert!(matches!(response, ClientResponse::Subscribed { board: id, .. } if id == board.id));
+
+    // Scores submitted over REST are pushed as well
+    let payload = SubmitScorePayload {
+        player: None,
+        value: Decimal::TEN,
+    };
+    let request = Request::builder()
+        .method("POST")
+        .uri(format!("/api/v1/leaderboard/{}/scores", board.id))
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", session.token))
+        .body(Body::from(serde_json::to_string(&payload)?))?;
+    let response = router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let event = receive(&mut socket).await?;
+    assert!(matches!(
+        event,
+        ClientResponse::ScoreUpdated { player, score, rank: 1, .. }
+            if player == user.id && score == Decimal::TEN
+    ));
+
+    let event = receive(&mut socket).await?;
+    assert!(matches!(
+        event,
+        ClientResponse::RankChanged {
+            previous_rank: None,
+            rank: 1,
+            ..
+        }
+    ));
+
+    let event = receive(&mut socket).await?;
+    assert!(matches!(
+        event,
+        ClientResponse::StandingsChanged {
+            from_rank: 1,
+            to_rank: None,
+            ..
+        }
+    ));
+
+    let response = send(&mut socket, &ClientMessage::Unsubscribe { board: board.id }).await?;
+    assert!(matches!(response, ClientResponse::Unsubscribed { .. }));
+
+    Ok(())
+}
+
+/// Register the owner of the default project and start a session
+async fn owner(state: &AppState) -> scoreboard::Result<(User, Session)> {
+    let email = "owner@example.com";
+    auth::create_user(DEFAULT_PROJECT, email, "correct horse", state.pool()).await?;
+    let owner = auth::promote_owner(DEFAULT_PROJECT, email, state.pool())
+        .await?
+        .unwrap();
+    let session = Session::create(owner.id, state.pool()).await?;
+
+    Ok((owner, session))
+}
+
+#[sqlx::test]
+async fn add_a_member_twice(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (_, session) = owner(&state).await?;
+    let player = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+
+    let address = serve(state.clone()).await?;
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(&url).await?;
+    let message = ClientMessage::AddMember {
+        board: board.id,
+        player: player.id,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(response, ClientResponse::AddMember { .. }));
+
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::Conflict,
+            ..
+        }
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn check_credentials_before_changes(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (owner, session) = owner(&state).await?;
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+
+    let address = serve(state.clone()).await?;
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(&url).await?;
+    let player = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let message = ClientMessage::AddMember {
+        board: board.id,
+        player: player.id,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(response, ClientResponse::AddMember { .. }));
+
+    // Losing the role takes effect on open connections
+    auth::set_role(DEFAULT_PROJECT, owner.id, Role::Player, state.pool()).await?;
+    let player = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    let message = ClientMessage::AddMember {
+        board: board.id,
+        player: player.id,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::Forbidden,
+            ..
+        }
+    ));
+
+    // So does logging out
+    Session::revoke(session.id, state.pool()).await?;
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::Unauthorized,
+            ..
+        }
+    ));
+
+    Ok(())
+}
+
+/// Create a member of a new board and sign them in
+async fn member(state: &AppState) -> scoreboard::Result<(Leaderboard, Session)> {
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+    let user = auth::create_anon_user(DEFAULT_PROJECT, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    Ok((board, session))
+}
+
+#[sqlx::test]
+async fn finish_requests_after_closing(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (board, session) = member(&state).await?;
+    let address = serve(state.clone()).await?;
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(url).await?;
+
+    let message = ClientMessage::UpdateScore {
+        board: board.id,
+        player: None,
+        score: Decimal::ONE,
+    };
+    let text = serde_json::to_string(&message)?;
+    socket.send(Message::Text(text.into())).await?;
+    socket.close(None).await?;
+
+    time::timeout(Duration::from_secs(1), async {
+        while board
+            .standings(&Default::default(), &Default::default(), state.pool())
+            .await?
+            .is_empty()
+        {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+        scoreboard::Result::Ok(())
+    })
+    .await
+    .expect("The score wasn't submitted")?;
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn authenticate_with_the_upgrade_request(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (board, session) = member(&state).await?;
+    let address = serve(state).await?;
+    let message = ClientMessage::UpdateScore {
+        board: board.id,
+        player: None,
+        score: Decimal::ONE,
+    };
+
+    let mut request = format!("ws://{address}/ws").into_client_request()?;
+    request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", session.token).parse().unwrap(),
+    );
+    let (mut socket, _) = connect_async(request).await?;
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(response, ClientResponse::UpdateScore { .. }));
+
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(url).await?;
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(response, ClientResponse::UpdateScore { .. }));
+
+    let result = connect_async(format!("ws://{address}/ws?token=invalid")).await;
+    assert!(matches!(
+        result,
+        Err(Error::Http(response)) if response.status() == StatusCode::UNAUTHORIZED
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn authenticate_with_a_message(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (board, session) = member(&state).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    let message = ClientMessage::Authenticate {
+        token: session.token.clone(),
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Authenticated { user: Some(user), project }
+            if user == session.user_id && project == DEFAULT_PROJECT
+    ));
+
+    let message = ClientMessage::UpdateScore {
+        board: board.id,
+        player: None,
+        score: Decimal::ONE,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(response, ClientResponse::UpdateScore { .. }));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn unauthenticated_sockets_are_read_only(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let (board, session) = member(&state).await?;
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    let response = send(&mut socket, &ClientMessage::Subscribe { board: board.id }).await?;
+    assert!(matches!(response, ClientResponse::Subscribed { .. }));
+
+    let message = ClientMessage::UpdateScore {
+        board: board.id,
+        player: Some(session.user_id),
+        score: Decimal::ONE,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::Unauthorized,
+            ..
+        }
+    ));
+
+    // Authenticating has to be the first message
+    let message = ClientMessage::Authenticate {
+        token: session.token,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::Unauthorized,
+            ..
+        }
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn ping_connections(pool: PgPool) -> scoreboard::Result<()> {
+    let config = SocketConfig {
+        ping_interval: Duration::from_millis(50),
+        idle_timeout: Duration::from_millis(200),
+    };
+    let state = AppState::with_pool(pool).await?.with_socket_config(config);
+    let address = serve(state).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    // Reading answers the pings so the connection stays open
+    let mut pings = 0;
+    let reading = time::timeout(Duration::from_millis(500), async {
+        while let Some(message) = socket.next().await {
+            match message? {
+                Message::Ping(_) => pings += 1,
+                Message::Close(frame) => panic!("Connection closed: {frame:?}"),
+                _ => {}
+            }
+        }
+        scoreboard::Result::Ok(())
+    });
+    assert!(reading.await.is_err());
+    assert!(pings >= 5);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn close_idle_connections(pool: PgPool) -> scoreboard::Result<()> {
+    let config = SocketConfig {
+        ping_interval: Duration::from_secs(10),
+        idle_timeout: Duration::from_millis(100),
+    };
+    let state = AppState::with_pool(pool).await?.with_socket_config(config);
+    let board = Leaderboard::new(DEFAULT_PROJECT, "Leaderboard", state.pool()).await?;
+    let address = serve(state.clone()).await?;
+    let (mut socket, _) = connect_async(format!("ws://{address}/ws")).await?;
+
+    let response = send(&mut socket, &ClientMessage::Subscribe { board: board.id }).await?;
+    assert!(matches!(response, ClientResponse::Subscribed { .. }));
+    assert_eq!(state.hub().subscriber_count(board.id), 1);
+
+    let frame = loop {
+        if let Message::Close(frame) = next_message(&mut socket).await? {
+            break frame.unwrap();
+        }
+    };
+    assert_eq!(u16::from(frame.code), CLOSE_IDLE_TIMEOUT);
+
+    // The subscriptions are removed with the connection
+    time::timeout(Duration::from_secs(1), async {
+        while state.hub().board_count() > 0 {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Subscriptions weren't removed");
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn resume_after_reconnecting(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    // A new project so the board's events aren't shared with other tests
+    let project = Project::create("Resume", state.pool()).await?;
+    let board = Leaderboard::new(project.id, "Leaderboard", state.pool()).await?;
+    let user = auth::create_anon_user(project.id, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    let address = serve(state.clone()).await?;
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(&url).await?;
+    let response = send(&mut socket, &ClientMessage::Subscribe { board: board.id }).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Subscribed { seq: 0, .. }
+    ));
+
+    hub::submit_score(state.hub(), &board, user.id, Decimal::ONE, state.pool()).await?;
+    let mut seqs = vec![];
+    while seqs.len() < 3 {
+        if let Message::Text(text) = next_message(&mut socket).await? {
+            let event: BoardEvent = serde_json::from_str(&text)?;
+            seqs.push(event.seq);
+        }
+    }
+    assert_eq!(seqs, vec![1, 2, 3]);
+    drop(socket);
+
+    // Only the events after the last one received are replayed
+    let (mut socket, _) = connect_async(&url).await?;
+    let message = ClientMessage::Resume {
+        board: board.id,
+        last_seq: 1,
+    };
+    let response = send(&mut socket, &message).await?;
+    let ClientResponse::Resumed { events, .. } = response else {
+        panic!("Expected the missed events, got {response:?}");
+    };
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].seq, 2);
+    assert!(matches!(
+        events[0].event,
+        ClientResponse::RankChanged { .. }
+    ));
+    assert!(matches!(
+        events[1].event,
+        ClientResponse::StandingsChanged { .. }
+    ));
+
+    let message = ClientMessage::Resume {
+        board: board.id,
+        last_seq: 10,
+    };
+    let response = send(&mut socket, &message).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::ResyncRequired { seq: 3, .. }
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn resume_after_a_submission_without_subscribers(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let project = Project::create("Resume", state.pool()).await?;
+    let board = Leaderboard::new(project.id, "Leaderboard", state.pool()).await?;
+    let user = auth::create_anon_user(project.id, state.pool()).await?;
+    board.add_member(user.id, state.pool()).await?;
+    let session = Session::create(user.id, state.pool()).await?;
+
+    let address = serve(state.clone()).await?;
+    let url = format!("ws://{address}/ws?token={}", session.token);
+    let (mut socket, _) = connect_async(&url).await?;
+    let response = send(&mut socket, &ClientMessage::Subscribe { board: board.id }).await?;
+    assert!(matches!(
+        response,
+        ClientResponse::Subscribed { seq: 0, .. }
+    ));
+    drop(socket);
+
+    // The only subscriber is gone while the score is submitted
+    time::timeout(Duration::from_secs(1), async {
+        while state.hub().board_count() > 0 {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The subscription wasn't removed");
+    hub::submit_score(state.hub(), &board, user.id, Decimal::ONE, state.pool()).await?;
+
+    let (mut socket, _) = connect_async(&url).await?;
+    let message = ClientMessage::Resume {
+        board: board.id,
+        last_seq: 0,
+    };
+    let response = send(&mut socket, &message).await?;
+    let ClientResponse::Resumed { events, .. } = response else {
+        panic!("Expected the missed events, got {response:?}");
+    };
+    let seqs: Vec<u64> = events.iter().map(|event| event.seq).collect();
+    assert_eq!(seqs, vec![1, 2, 3]);
+    assert!(matches!(
+        events[0].event,
+        ClientResponse::ScoreUpdated { .. }
+    ));
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn negotiate_message_pack(pool: PgPool) -> scoreboard::Result<()> {
+    let state = AppState::with_pool(pool).await?;
+    let address = serve(state).await?;
+
+    let mut request = format!("ws://{address}/ws").into_client_request()?;
+    request
+        .headers_mut()
+        .insert("Sec-WebSocket-Protocol", MSGPACK_PROTOCOL.parse().unwrap());
+    let (mut socket, response) = connect_async(request).await?;
+    assert_eq!(
+        response.headers()["Sec-WebSocket-Protocol"],
+        MSGPACK_PROTOCOL
+    );
+
+    let message = Envelope::new(Some(MessageId::Number(7)), ClientMessage::CreateScoreBoard);
+    let bytes = rmp_serde::to_vec_named(&message).unwrap();
+    socket.send(Message::Binary(bytes.into())).await?;
+
+    let response = loop {
+        if let Message::Binary(bytes) = next_message(&mut socket).await? {
+            break rmp_serde::from_slice::<Envelope<ClientResponse>>(&bytes).unwrap();
+        }
+    };
+    assert_eq!(response.id, Some(MessageId::Number(7)));
+    let ClientResponse::CreateScoreBoard { id } = response.message else {
+        panic!("Expected a scoreboard, got {:?}", response.message);
+    };
+
+    let message = Envelope::new(None, ClientMessage::GetScoreBoard { id });
+    let bytes = rmp_serde::to_vec_named(&message).unwrap();
+    socket.send(Message::Binary(bytes.into())).await?;
+    let response = loop {
+        if let Message::Binary(bytes) = next_message(&mut socket).await? {
+            break rmp_serde::from_slice::<ClientResponse>(&bytes).unwrap();
+        }
+    };
+    assert!(matches!(response, ClientResponse::GetScoreBoard { .. }));
+
+    // Text frames are rejected on msgpack connections
+    socket.send(Message::Text("{}".into())).await?;
+    let response = loop {
+        if let Message::Binary(bytes) = next_message(&mut socket).await? {
+            break rmp_serde::from_slice::<ClientResponse>(&bytes).unwrap();
+        }
+    };
+    assert!(matches!(
+        response,
+        ClientResponse::Error {
+            kind: ClientErrorKind::InvalidInput,
+            ..
+        }
+    ));
+
+    Ok(())
+}